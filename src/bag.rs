@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use material::MaterialId;
+
+pub struct Bag {
+    items: HashMap<MaterialId, u32>,
+}
+
+impl Bag {
+    pub fn new() -> Bag {
+        Bag {
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, id: MaterialId, n: u32) {
+        *self.items.entry(id).or_insert(0) += n;
+    }
+
+    pub fn take(&mut self, id: MaterialId) -> bool {
+        match self.items.get_mut(&id) {
+            Some(n) if *n > 0 => { *n -= 1; true },
+            _ => false,
+        }
+    }
+
+    pub fn count(&self, id: MaterialId) -> u32 {
+        *self.items.get(&id).unwrap_or(&0)
+    }
+}
@@ -0,0 +1,114 @@
+extern crate carvel;
+extern crate image;
+
+use std::path::Path;
+
+use carvel::cli::arg;
+use carvel::config::GenConfig;
+use carvel::gen::{self, WorldGenerator};
+use carvel::material::MaterialId;
+use carvel::world::Spot;
+
+// Renders cross-sections of a generated world to PNG, no window needed:
+//
+//   cargo run --bin preview -- --seed 42 --at 0,-40,0 --size 256 --out slice
+//
+// writes slice-{top,side}-{colors,materials,caves}.png. The top cut is level
+// at y, the side cut runs along x through z, both centred on --at.
+
+fn rgb(c: [f32; 4]) -> [u8; 3] {
+    let b = |v: f32| (v.max(0.0).min(1.0) * 255.0) as u8;
+    [b(c[0]), b(c[1]), b(c[2])]
+}
+
+// A color per material, spread around the hue circle so neighbours differ
+// even where the materials' own colors are alike.
+fn material_color(id: MaterialId) -> [u8; 3] {
+    let h = (id as f32 * 0.618034).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    rgb([0.2 + 0.7 * r, 0.2 + 0.7 * g, 0.2 + 0.7 * b, 1.0])
+}
+
+struct Slice {
+    colors: Vec<u8>,
+    materials: Vec<u8>,
+    caves: Vec<u8>,
+}
+
+// Samples size by size spots, pos(column, row) giving the spot for each
+// pixel with row 0 at the top.
+fn slice<F>(gen: &dyn WorldGenerator, size: u32, pos: F) -> Slice
+    where F: Fn(i32, i32) -> (i32, i32, i32) {
+    let n = (size * size * 3) as usize;
+    let mut s = Slice { colors: Vec::with_capacity(n), materials: Vec::with_capacity(n),
+                        caves: Vec::with_capacity(n) };
+    for row in 0..size as i32 {
+        for col in 0..size as i32 {
+            let (x, y, z) = pos(col, row);
+            let (color, material, cave) = match gen.spot(x, y, z) {
+                Spot::Rich(b) => {
+                    let cave = match gen.fluid_at(x, y, z) {
+                        Some(f) => rgb(f.color()),
+                        None => [40, 40, 40],
+                    };
+                    (rgb(b.color()), material_color(b.material_id()), cave)
+                },
+                _ => ([0, 0, 0], [0, 0, 0], [255, 255, 255]),
+            };
+            s.colors.extend_from_slice(&color);
+            s.materials.extend_from_slice(&material);
+            s.caves.extend_from_slice(&cave);
+        }
+    }
+    s
+}
+
+fn save(out: &str, cut: &str, s: &Slice, size: u32) {
+    for &(name, data) in [("colors", &s.colors), ("materials", &s.materials),
+                          ("caves", &s.caves)].iter() {
+        let path = format!("{}-{}-{}.png", out, cut, name);
+        if let Err(e) = image::save_buffer(&path, data, size, size, image::RGB(8)) {
+            panic!("Could not write {}: {}", path, e);
+        }
+        println!("Wrote {}", path);
+    }
+}
+
+fn main() {
+    let seed = arg("seed").map_or(0, |s| s.parse().expect("--seed needs a number"));
+    let generator = arg("generator").unwrap_or(gen::DEFAULT_GENERATOR.to_string());
+    let config = match arg("gen-config") {
+        Some(path) => match GenConfig::load(Path::new(&path)) {
+            Ok(config) => config,
+            Err(e) => panic!("Could not load generator config {}: {}", path, e),
+        },
+        None => GenConfig::default(),
+    };
+    let at: Vec<i32> = arg("at").map_or(vec![0, -32, 0], |s| {
+        s.split(',').map(|v| v.trim().parse().expect("--at needs x,y,z")).collect()
+    });
+    if at.len() != 3 { panic!("--at needs x,y,z"); }
+    let size: u32 = arg("size").map_or(256, |s| s.parse().expect("--size needs a number"));
+    let out = arg("out").unwrap_or("preview".to_string());
+
+    let gen = match gen::by_name(&generator, seed, &config) {
+        Some(g) => g,
+        None => panic!("Unknown world generator {}, try one of {}",
+                       generator, gen::GENERATORS.join(", ")),
+    };
+    println!("Seed {}, generator {}, at {},{},{}", seed, generator, at[0], at[1], at[2]);
+
+    let half = size as i32 / 2;
+    let top = slice(&*gen, size, |c, r| (at[0] - half + c, at[1], at[2] - half + r));
+    save(&out, "top", &top, size);
+    let side = slice(&*gen, size, |c, r| (at[0] - half + c, at[1] + half - 1 - r, at[2]));
+    save(&out, "side", &side, size);
+}
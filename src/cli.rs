@@ -0,0 +1,16 @@
+use std::env;
+
+// Reads `--<name> <value>` from the command line.
+pub fn arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            match args.next() {
+                Some(value) => return Some(value),
+                None => panic!("{} needs a value", flag),
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,346 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use material;
+use structure;
+use toml;
+
+// Everything the noise generator can be tuned by. A file only needs the
+// top level entries it changes, the rest keep their defaults, and the
+// defaults make the same world as an untuned generator. TOML wants plain
+// values ahead of tables, so fields are in that order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenConfig {
+    // How far the color noise moves a block away from its base color.
+    pub speckle: f32,
+    // Noise above these levels holds water or lava, lava below lava_depth.
+    pub wet_level: f32,
+    pub magma_wet_level: f32,
+    pub lava_depth: i32,
+    pub tunnel_width: f32,
+    pub cavern_depth: i32,
+    pub cavern_level: f32,
+    // Soil noise above this holds the region's loose material.
+    pub loose_level: f32,
+    pub color: NoiseLayer,
+    pub wet: NoiseLayer,
+    pub tunnels: NoiseLayer,
+    pub caverns: NoiseLayer,
+    pub region_noise: NoiseLayer,
+    pub loose: NoiseLayer,
+    pub geodes: Geodes,
+    pub structures: Structures,
+    pub strata: Strata,
+    // Color multipliers by height, blended between the stops.
+    pub ramp: Vec<RampStop>,
+    pub regions: Vec<Region>,
+    pub ores: Vec<Ore>,
+}
+
+// One noise. The seed is added to the world seed, scale is the number of
+// blocks across one feature.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseLayer {
+    pub seed: usize,
+    pub scale: f32,
+    pub octaves: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RampStop {
+    pub y: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Geodes {
+    pub cell: i32,
+    pub odds: u64,
+    pub depth: i32,
+}
+
+// At most one structure in each cell this many blocks across, in one of
+// odds cells below ground level.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Structures {
+    pub cell: i32,
+    pub odds: u64,
+}
+
+// Depths where soil gives way to sediment, sediment to bedrock and bedrock
+// to the magma zone.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Strata {
+    pub soil: i32,
+    pub sediment: i32,
+    pub bedrock: i32,
+    pub wobble: f32,
+    pub bedrock_material: String,
+    pub magma_material: String,
+    pub wobble_noise: NoiseLayer,
+}
+
+// A region covers the region noise up to below, the last one the rest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub below: f32,
+    pub soil: String,
+    pub loose: String,
+    pub sediment: String,
+    pub tint: [f32; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deposit {
+    // Thin sheets along the creases of the noise.
+    Vein,
+    // Round pockets where the noise peaks.
+    Cluster,
+}
+
+// Ores grow richer from nothing at top to their full size depth below it,
+// where they take up size of the noise range. Earlier ores win overlaps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ore {
+    pub material: String,
+    pub deposit: Deposit,
+    pub top: i32,
+    pub depth: i32,
+    pub size: f32,
+    pub noise: NoiseLayer,
+}
+
+fn layer(seed: usize, scale: f32, octaves: usize) -> NoiseLayer {
+    NoiseLayer { seed, scale, octaves }
+}
+
+fn region(name: &str, below: f32, soil: &str, loose: &str, sediment: &str, tint: [f32; 3])
+-> Region {
+    Region {
+        name: name.to_string(),
+        below,
+        soil: soil.to_string(),
+        loose: loose.to_string(),
+        sediment: sediment.to_string(),
+        tint,
+    }
+}
+
+fn ore(material: &str, deposit: Deposit, noise: NoiseLayer, top: i32, depth: i32, size: f32)
+-> Ore {
+    Ore { material: material.to_string(), deposit, top, depth, size, noise }
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig {
+            speckle: 0.15,
+            wet_level: 0.6,
+            magma_wet_level: 0.35,
+            lava_depth: -48,
+            tunnel_width: 0.05,
+            cavern_depth: -32,
+            cavern_level: 0.6,
+            loose_level: 0.5,
+            color: layer(0, 100.0, 6),
+            wet: layer(3, 16.0, 6),
+            tunnels: layer(8, 40.0, 2),
+            caverns: layer(10, 64.0, 3),
+            region_noise: layer(12, 256.0, 2),
+            loose: layer(13, 8.0, 2),
+            geodes: Geodes { cell: 24, odds: 12, depth: -24 },
+            structures: Structures { cell: 64, odds: 3 },
+            strata: Strata {
+                soil: -8,
+                sediment: -40,
+                bedrock: -120,
+                wobble: 6.0,
+                bedrock_material: "basalt".to_string(),
+                magma_material: "magma".to_string(),
+                wobble_noise: layer(11, 32.0, 2),
+            },
+            ramp: vec![RampStop { y: 0.0, color: [1.0, 1.0, 1.0] }],
+            regions: vec![
+                region("dunes", -0.25, "sandstone", "sand", "sandstone", [1.05, 0.95, 0.85]),
+                region("lowlands", 0.25, "dirt", "gravel", "stone", [0.95, 1.0, 0.95]),
+                region("highlands", 1.0, "stone", "gravel", "slate", [0.9, 0.95, 1.05]),
+            ],
+            ores: vec![
+                ore("gold", Deposit::Cluster, layer(4, 6.0, 2), -48, 128, 0.2),
+                ore("iron", Deposit::Vein, layer(5, 32.0, 2), -16, 96, 0.025),
+                ore("copper", Deposit::Vein, layer(6, 24.0, 2), 0, 64, 0.03),
+                ore("coal", Deposit::Cluster, layer(7, 8.0, 2), 16, 48, 0.3),
+            ],
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl GenConfig {
+    pub fn load(path: &Path) -> io::Result<GenConfig> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        GenConfig::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> io::Result<GenConfig> {
+        let config: GenConfig = toml::from_str(text)
+            .map_err(|e| invalid(format!("bad generator config: {}", e)))?;
+        config.check()?;
+        Ok(config)
+    }
+
+    pub fn to_text(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(|e| invalid(format!("bad generator config: {}", e)))
+    }
+
+    // Catches what would otherwise only fail deep inside generation.
+    fn check(&self) -> io::Result<()> {
+        let mut names = vec![&self.strata.bedrock_material, &self.strata.magma_material];
+        for r in self.regions.iter() {
+            names.push(&r.soil);
+            names.push(&r.loose);
+            names.push(&r.sediment);
+        }
+        for o in self.ores.iter() {
+            names.push(&o.material);
+        }
+        for name in names.into_iter() {
+            if material::by_name(name).is_none() {
+                return Err(invalid(format!("unknown material {}", name)));
+            }
+        }
+        if self.regions.is_empty() {
+            return Err(invalid("at least one region is needed".to_string()));
+        }
+        if self.ramp.is_empty() {
+            return Err(invalid("the color ramp needs at least one stop".to_string()));
+        }
+        if self.geodes.cell < 12 || self.geodes.odds == 0 {
+            return Err(invalid("geode cells must be at least 12 wide with odds above 0".to_string()));
+        }
+        if self.structures.cell < structure::MAX_EXTENT || self.structures.odds == 0 {
+            return Err(invalid(format!("structure cells must be at least {} wide with odds above 0",
+                                       structure::MAX_EXTENT)));
+        }
+
+        let mut layers = vec![
+            ("color".to_string(), &self.color),
+            ("wet".to_string(), &self.wet),
+            ("tunnels".to_string(), &self.tunnels),
+            ("caverns".to_string(), &self.caverns),
+            ("region_noise".to_string(), &self.region_noise),
+            ("loose".to_string(), &self.loose),
+            ("strata.wobble_noise".to_string(), &self.strata.wobble_noise),
+        ];
+        for o in self.ores.iter() {
+            layers.push((format!("ores.{}.noise", o.material), &o.noise));
+        }
+        for (name, layer) in layers.into_iter() {
+            // Noise is sampled at position / scale.
+            if !(layer.scale > 0.0) {
+                return Err(invalid(format!("{}.scale must be above 0", name)));
+            }
+            if layer.octaves == 0 {
+                return Err(invalid(format!("{}.octaves must be at least 1", name)));
+            }
+        }
+        for o in self.ores.iter() {
+            if o.depth <= 0 {
+                return Err(invalid(format!("ores.{}.depth must be above 0", o.material)));
+            }
+        }
+        // Colors are blended across the gap between neighbouring stops.
+        let mut ys: Vec<f32> = self.ramp.iter().map(|r| r.y).collect();
+        ys.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        if let Some(w) = ys.windows(2).find(|w| !(w[0] < w[1])) {
+            return Err(invalid(format!("ramp.y must differ between stops, {} is used twice", w[0])));
+        }
+        let strata = &self.strata;
+        if !(strata.soil > strata.sediment && strata.sediment > strata.bedrock) {
+            return Err(invalid("strata.soil, strata.sediment and strata.bedrock must go down \
+                                in that order".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fails(text: &str, names: &str) {
+        match GenConfig::parse(text) {
+            Ok(_) => panic!("accepted {:?}", text),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                let msg = e.to_string();
+                assert!(msg.contains(names), "{:?} does not name {}", msg, names);
+            },
+        }
+    }
+
+    const STRATA: &'static str = "
+        [strata]
+        soil = -8
+        sediment = -40
+        bedrock = -120
+        wobble = 6.0
+        bedrock_material = \"basalt\"
+        magma_material = \"magma\"
+        wobble_noise = { seed = 11, scale = 32.0, octaves = 2 }
+    ";
+
+    #[test]
+    fn parse_round_trip() {
+        let config = GenConfig::parse("").unwrap();
+        assert_eq!(config.lava_depth, GenConfig::default().lava_depth);
+        let text = GenConfig::default().to_text().unwrap();
+        assert_eq!(GenConfig::parse(&text).unwrap().to_text().unwrap(), text);
+
+        let config = GenConfig::parse("speckle = 0.5\n[tunnels]\nseed = 1\nscale = 9.0\noctaves = 1")
+            .unwrap();
+        assert_eq!((config.speckle, config.tunnels.scale), (0.5, 9.0));
+        assert_eq!(config.caverns.scale, GenConfig::default().caverns.scale);
+        GenConfig::parse(STRATA).unwrap();
+    }
+
+    #[test]
+    fn parse_errors() {
+        fails("speckle = \"lots\"", "bad generator config");
+        fails("speckle = ", "bad generator config");
+        fails("[color]\nseed = 0\nscale = 1.0", "bad generator config");
+        fails("regions = []", "region");
+        fails("ramp = []", "ramp");
+        fails("[geodes]\ncell = 4\nodds = 1\ndepth = 0", "geode");
+        fails("[structures]\ncell = 1\nodds = 1", "structure");
+        fails(&STRATA.replace("basalt", "cheese"), "unknown material cheese");
+    }
+
+    #[test]
+    fn check_names_field() {
+        fails("[color]\nseed = 0\nscale = 0.0\noctaves = 6", "color.scale");
+        fails("[loose]\nseed = 0\nscale = -2.0\noctaves = 6", "loose.scale");
+        fails("[wet]\nseed = 0\nscale = 4.0\noctaves = 0", "wet.octaves");
+        fails(&STRATA.replace("scale = 32.0", "scale = 0.0"), "strata.wobble_noise.scale");
+        fails(&STRATA.replace("sediment = -40", "sediment = -200"), "strata.soil");
+
+        let gold = "[[ores]]\nmaterial = \"gold\"\ndeposit = \"cluster\"\ntop = 0\n\
+                    depth = 64\nsize = 0.1\nnoise = { seed = 4, scale = 6.0, octaves = 2 }";
+        GenConfig::parse(gold).unwrap();
+        fails(&gold.replace("depth = 64", "depth = 0"), "ores.gold.depth");
+        fails(&gold.replace("octaves = 2", "octaves = 0"), "ores.gold.noise.octaves");
+
+        let ramp = "[[ramp]]\ny = 4.0\ncolor = [1.0, 1.0, 1.0]\n\
+                    [[ramp]]\ny = -4.0\ncolor = [0.5, 0.5, 0.5]";
+        GenConfig::parse(ramp).unwrap();
+        fails(&ramp.replace("-4.0", "4.0"), "ramp.y");
+    }
+}
@@ -6,6 +6,8 @@ use input::mouse::MouseButton;
 
 use tool;
 use tool::InteractionState;
+use world::Milieu;
+use bag::Bag;
 
 struct Control {
     button: Button,
@@ -41,6 +43,16 @@ pub struct PlayerController {
     booster: Control,
     break_block: Control,
     place_block: Control,
+    next_material: Control,
+    undo: Control,
+    redo: Control,
+    select: Control,
+    copy: Control,
+    paste: Control,
+    rotate_clip: Control,
+    mirror_clip: Control,
+    save_clip: Control,
+    load_clip: Control,
     drop_player: Control,
     drop_camera: Control,
     mouse_sensitivity_horizontal: f32,
@@ -61,6 +73,16 @@ impl PlayerController
             booster:         Control::new(Keyboard(Key::LCtrl)),
             break_block:     Control::new(Mouse(MouseButton::Left)),
             place_block:     Control::new(Mouse(MouseButton::Right)),
+            next_material:   Control::new(Keyboard(Key::Tab)),
+            undo:            Control::new(Keyboard(Key::Z)),
+            redo:            Control::new(Keyboard(Key::Y)),
+            select:          Control::new(Keyboard(Key::B)),
+            copy:            Control::new(Keyboard(Key::C)),
+            paste:           Control::new(Keyboard(Key::V)),
+            rotate_clip:     Control::new(Keyboard(Key::Q)),
+            mirror_clip:     Control::new(Keyboard(Key::X)),
+            save_clip:       Control::new(Keyboard(Key::F5)),
+            load_clip:       Control::new(Keyboard(Key::F6)),
             drop_player:     Control::new(Keyboard(Key::F7)),
             drop_camera:     Control::new(Keyboard(Key::F8)),
 
@@ -100,7 +122,8 @@ impl PlayerController {
         player.pitch = (player.pitch).min(PI / 2.0).max(-PI / 2.0);
     }
 
-    pub fn input(&mut self, button: Button, on: bool, player: &mut Player, tool: &mut tool::Tool) {
+    pub fn input(&mut self, button: Button, on: bool, player: &mut Player, tool: &mut tool::Tool,
+                 bag: &mut Bag, m: &mut Milieu) {
 
         let &mut Player {
             ref mut dir,
@@ -141,14 +164,43 @@ impl PlayerController {
                 }},
             x if self.booster.flip(x, on) => { self.booster.flop(); },
             x if self.break_block.flip(x, on) => { self.break_block.flop();
-                if on { tool.state = InteractionState::Mining;
-                        if tool.clock < 0.0 { tool.clock = 0.0; } }
-                else  { tool.state = InteractionState::Idle; }
+                if on { tool.state = InteractionState::Mining; m.begin_edit(); }
+                else  { tool.stop(); m.end_edit(); }
             },
             x if self.place_block.flip(x, on) => { self.place_block.flop();
-                if on { tool.state = InteractionState::Placing;
+                if on { tool.state = InteractionState::Placing; m.begin_edit();
                         if tool.clock < 0.0 { tool.clock = 0.0; } }
-                else  { tool.state = InteractionState::Idle; }
+                else  { tool.stop(); m.end_edit(); }
+            },
+            x if self.next_material.flip(x, on) => { self.next_material.flop();
+                if on { tool.next_material(); }
+            },
+            x if self.undo.flip(x, on) => { self.undo.flop();
+                if on { tool.undo(m, bag); }
+            },
+            x if self.redo.flip(x, on) => { self.redo.flop();
+                if on { tool.redo(m, bag); }
+            },
+            x if self.select.flip(x, on) => { self.select.flop();
+                if on { tool.select(m, player); }
+            },
+            x if self.copy.flip(x, on) => { self.copy.flop();
+                if on { tool.copy(m); }
+            },
+            x if self.paste.flip(x, on) => { self.paste.flop();
+                if on { tool.paste(m, player); }
+            },
+            x if self.rotate_clip.flip(x, on) => { self.rotate_clip.flop();
+                if on { tool.rotate_clip(); }
+            },
+            x if self.mirror_clip.flip(x, on) => { self.mirror_clip.flop();
+                if on { tool.mirror_clip(); }
+            },
+            x if self.save_clip.flip(x, on) => { self.save_clip.flop();
+                if on { tool.save_clip(); }
+            },
+            x if self.load_clip.flip(x, on) => { self.load_clip.flop();
+                if on { tool.load_clip(); }
             },
             x if self.drop_player.flip(x, on) => { self.drop_player.flop(); if on {
                 if *noclip { *noclip = false; *pos = cam.clone(); }
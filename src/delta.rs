@@ -0,0 +1,99 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Iter;
+
+use world::Spot;
+
+// Spots that differ from what the generator would produce, grouped by chunk
+// and keyed by the spot's index within that chunk.
+pub struct Delta {
+    chunks: HashMap<(i32, i32, i32), HashMap<u16, Spot>>,
+    changed: HashSet<(i32, i32, i32)>,
+}
+
+// A position and its six neighbours.
+pub const AROUND: [(i32, i32, i32); 7] =
+    [(0,0,0), (1,0,0), (-1,0,0), (0,1,0), (0,-1,0), (0,0,1), (0,0,-1)];
+
+pub fn split(x: i32, y: i32, z: i32) -> ((i32, i32, i32), u16) {
+    let (bx, by, bz) = (x >> 4, y >> 4, z >> 4);
+    let (sx, sy, sz) = (x & 15, y & 15, z & 15);
+    ((bx, by, bz), ((sx << 8) | (sy << 4) | sz) as u16)
+}
+
+impl Delta {
+    pub fn new() -> Delta {
+        Delta {
+            chunks: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<&Spot> {
+        let (chunk, i) = split(x, y, z);
+        self.chunks.get(&chunk).and_then(|c| c.get(&i))
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, s: Spot) {
+        let (chunk, i) = split(x, y, z);
+        self.chunks.entry(chunk).or_insert_with(HashMap::new).insert(i, s);
+        self.changed.insert(chunk);
+    }
+
+    pub fn clear(&mut self, x: i32, y: i32, z: i32) {
+        let (chunk, i) = split(x, y, z);
+        let emptied = match self.chunks.get_mut(&chunk) {
+            Some(c) => {
+                if c.remove(&i).is_none() { return; }
+                c.is_empty()
+            },
+            None => return,
+        };
+        if emptied { self.chunks.remove(&chunk); }
+        self.changed.insert(chunk);
+    }
+
+    pub fn chunk(&self, x: i32, y: i32, z: i32) -> Option<&HashMap<u16, Spot>> {
+        self.chunks.get(&(x, y, z))
+    }
+
+    pub fn insert_chunk(&mut self, pos: (i32, i32, i32), spots: HashMap<u16, Spot>) {
+        if !spots.is_empty() {
+            self.chunks.insert(pos, spots);
+        }
+    }
+
+    // A copy of the changes in a chunk and its six neighbours, which is all
+    // that rebuilding that chunk reads.
+    pub fn around(&self, x: i32, y: i32, z: i32) -> Delta {
+        let mut d = Delta::new();
+        for &(dx, dy, dz) in AROUND.iter() {
+            let pos = (x + dx, y + dy, z + dz);
+            if let Some(spots) = self.chunks.get(&pos) {
+                d.chunks.insert(pos, spots.clone());
+            }
+        }
+        d
+    }
+
+    pub fn chunks(&self) -> Iter<(i32, i32, i32), HashMap<u16, Spot>> {
+        self.chunks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.values().map(|c| c.len()).sum()
+    }
+
+    // Forgets a chunk that was written back, along with its being changed.
+    pub fn remove_chunk(&mut self, pos: (i32, i32, i32)) {
+        self.chunks.remove(&pos);
+        self.changed.remove(&pos);
+    }
+
+    pub fn is_changed(&self, pos: (i32, i32, i32)) -> bool {
+        self.changed.contains(&pos)
+    }
+
+    pub fn take_changed(&mut self) -> Vec<(i32, i32, i32)> {
+        self.changed.drain().collect()
+    }
+}
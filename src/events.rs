@@ -0,0 +1,40 @@
+use std::sync::mpsc::{channel, Sender, Receiver};
+
+use world::Spot;
+
+// Unrevealed ground is reported as the block it would reveal, never as Full.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+    BlockRemoved { pos: (i32, i32, i32), old: Spot },
+    BlockPlaced { pos: (i32, i32, i32), old: Spot, new: Spot },
+    ChunkCreated { pos: (i32, i32, i32) },
+    ChunkRemeshed { pos: (i32, i32, i32) },
+}
+
+// Every subscriber gets its own channel, so it can drain events on whatever
+// thread it likes. Subscribers that hung up are dropped on the next emit.
+pub struct Observers {
+    senders: Vec<Sender<WorldEvent>>,
+}
+
+impl Observers {
+    pub fn new() -> Observers {
+        Observers {
+            senders: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<WorldEvent> {
+        let (tx, rx) = channel();
+        self.senders.push(tx);
+        rx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    pub fn emit(&mut self, event: WorldEvent) {
+        self.senders.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,40 @@
+use world::Block;
+
+const GRAVITY:  f32 = 20.0;
+const TERMINAL: f32 = 30.0;
+
+// A block that lost its support. It keeps its column and only moves down,
+// y being the height of its bottom face.
+#[derive(Clone, Debug)]
+pub struct FallingBlock {
+    pub block: Block,
+    pub x: i32,
+    pub y: f32,
+    pub z: i32,
+    pub vel: f32,
+}
+
+impl FallingBlock {
+    pub fn new(block: Block, x: i32, y: i32, z: i32) -> FallingBlock {
+        FallingBlock {
+            block,
+            x,
+            y: y as f32,
+            z,
+            vel: 0.0,
+        }
+    }
+
+    // Where the block would be after dt if nothing were in the way.
+    pub fn next_y(&mut self, dt: f32) -> f32 {
+        self.vel = (self.vel + GRAVITY * dt).min(TERMINAL);
+        self.y - self.vel * dt
+    }
+
+    // Whether the block at height y overlaps the box from min to max.
+    pub fn overlaps(&self, y: f32, min: [f64;3], max: [f64;3]) -> bool {
+        (self.x as f64) < max[0] && (self.x + 1) as f64 > min[0]
+        && (y as f64) < max[1] && (y + 1.0) as f64 > min[1]
+        && (self.z as f64) < max[2] && (self.z + 1) as f64 > min[2]
+    }
+}
@@ -0,0 +1,102 @@
+use world::Block;
+use material::{self, MaterialId};
+use gen::WorldGenerator;
+
+// The same material everywhere, with no caves or fluids. Handy for tests.
+pub struct Flat {
+    material: MaterialId,
+}
+
+impl Flat {
+    pub fn new(material: MaterialId) -> Flat {
+        Flat { material }
+    }
+}
+
+impl WorldGenerator for Flat {
+    fn at(&self, _x: i32, _y: i32, _z: i32) -> Block {
+        Block::new(self.material, 0, material::get(self.material).color)
+    }
+}
+
+// Level layers of (material, thickness) going down from y = 0. The first
+// layer also fills everything above, the last everything below.
+pub struct Superflat {
+    layers: Vec<(MaterialId, i32)>,
+}
+
+impl Superflat {
+    pub fn new(layers: Vec<(MaterialId, i32)>) -> Superflat {
+        assert!(!layers.is_empty(), "superflat needs at least one layer");
+        Superflat { layers }
+    }
+    pub fn standard() -> Superflat {
+        Superflat::new(vec![
+            (material::DIRT, 4),
+            (material::STONE, 32),
+            (material::BASALT, 64),
+            (material::MAGMA, 1),
+        ])
+    }
+    fn layer_at(&self, y: i32) -> MaterialId {
+        let mut top = 0;
+        for &(m, thickness) in self.layers.iter() {
+            if y > top - thickness { return m; }
+            top -= thickness;
+        }
+        self.layers[self.layers.len() - 1].0
+    }
+}
+
+impl WorldGenerator for Superflat {
+    fn at(&self, _x: i32, y: i32, _z: i32) -> Block {
+        let m = self.layer_at(y);
+        Block::new(m, 0, material::get(m).color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::GenConfig;
+    use gen;
+
+    #[test]
+    fn superflat_layers() {
+        let s = Superflat::standard();
+        // The first layer also fills everything above ground.
+        assert_eq!(s.layer_at(40), material::DIRT);
+        assert_eq!(s.layer_at(0), material::DIRT);
+        assert_eq!(s.layer_at(-3), material::DIRT);
+        assert_eq!(s.layer_at(-4), material::STONE);
+        assert_eq!(s.layer_at(-35), material::STONE);
+        assert_eq!(s.layer_at(-36), material::BASALT);
+        assert_eq!(s.layer_at(-99), material::BASALT);
+        assert_eq!(s.layer_at(-100), material::MAGMA);
+        // And the last one everything below.
+        assert_eq!(s.layer_at(-101), material::MAGMA);
+        assert_eq!(s.layer_at(-5000), material::MAGMA);
+    }
+
+    #[test]
+    fn flat_everywhere() {
+        let f = Flat::new(material::SLATE);
+        for &(x, y, z) in [(0, 0, 0), (-7, 300, 12), (40, -900, -3)].iter() {
+            assert_eq!(f.at(x, y, z).material_id(), material::SLATE);
+            assert!(!f.is_cave(x, y, z));
+            assert!(f.fluid_at(x, y, z).is_none());
+        }
+    }
+
+    #[test]
+    fn generators_by_name() {
+        let config = GenConfig::default();
+        for name in gen::GENERATORS.iter() {
+            assert!(gen::by_name(name, 1, &config).is_some(), "{}", name);
+        }
+        assert!(gen::by_name("nonsense", 1, &config).is_none());
+        assert!(gen::by_name("", 1, &config).is_none());
+        let flat = gen::by_name("flat", 1, &config).unwrap();
+        assert_eq!(flat.at(3, -20, 5).material_id(), material::STONE);
+    }
+}
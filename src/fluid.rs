@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+
+use gfx_voxel::cube;
+use delta;
+use world::{self, InfiniteWorld, Spot, Vertex};
+
+pub const MAX_LEVEL: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    pub fn color(&self) -> [f32; 4] {
+        match *self {
+            Fluid::Water => [0.2, 0.35, 0.8, 1.0],
+            Fluid::Lava => [1.0, 0.4, 0.1, 1.0],
+        }
+    }
+    // Ticks between moves, so thick fluids spread slower.
+    pub fn pace(&self) -> u32 {
+        match *self {
+            Fluid::Water => 1,
+            Fluid::Lava => 4,
+        }
+    }
+    // Share of a body's velocity the fluid takes away each update.
+    pub fn drag(&self) -> f32 {
+        match *self {
+            Fluid::Water => 0.1,
+            Fluid::Lava => 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub fluid: Fluid,
+    pub level: u8,
+}
+
+// Fluid lives in empty spots, in levels from 1 to MAX_LEVEL. Only cells that
+// changed recently, and their neighbours, are stepped on a tick.
+pub struct Fluids {
+    cells: HashMap<(i32, i32, i32), Cell>,
+    active: HashSet<(i32, i32, i32)>,
+    ticks: u32,
+    changed: bool,
+    // Chunks whose cells changed since they were last saved.
+    touched: HashSet<(i32, i32, i32)>,
+}
+
+const SIDES: [(i32, i32, i32); 4] = [(1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+
+fn open(world: &InfiniteWorld, x: i32, y: i32, z: i32) -> bool {
+    if let Some(&Spot::Empty) = world.at(x, y, z) { true } else { false }
+}
+
+impl Fluids {
+    pub fn new() -> Fluids {
+        Fluids {
+            cells: HashMap::new(),
+            active: HashSet::new(),
+            ticks: 0,
+            changed: false,
+            touched: HashSet::new(),
+        }
+    }
+
+    pub fn at(&self, x: i32, y: i32, z: i32) -> Option<Cell> {
+        self.cells.get(&(x, y, z)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, cell: Option<Cell>) {
+        match cell {
+            Some(c) if c.level > 0 => { self.cells.insert((x, y, z), c); },
+            _ => { self.cells.remove(&(x, y, z)); },
+        }
+        self.wake_around(x, y, z);
+        self.changed = true;
+        self.touched.insert(delta::split(x, y, z).0);
+    }
+
+    // The cells in each of the chunks, keyed by the spot's index within the
+    // chunk like the delta.
+    pub fn chunks(&self, chunks: &HashSet<(i32, i32, i32)>)
+    -> HashMap<(i32, i32, i32), HashMap<u16, Cell>> {
+        let mut found: HashMap<_, HashMap<u16, Cell>> =
+            chunks.iter().map(|&pos| (pos, HashMap::new())).collect();
+        for (&(x, y, z), &c) in self.cells.iter() {
+            let (pos, i) = delta::split(x, y, z);
+            if let Some(cells) = found.get_mut(&pos) { cells.insert(i, c); }
+        }
+        found
+    }
+
+    // Drops the cells of chunks that were written back.
+    pub fn forget(&mut self, chunks: &HashSet<(i32, i32, i32)>) {
+        let inside = |&(x, y, z): &(i32, i32, i32)| chunks.contains(&delta::split(x, y, z).0);
+        let before = self.cells.len();
+        self.cells.retain(|p, _| !inside(p));
+        self.active.retain(|p| !inside(p));
+        self.touched.retain(|pos| !chunks.contains(pos));
+        if self.cells.len() != before { self.changed = true; }
+    }
+
+    pub fn is_touched(&self, pos: (i32, i32, i32)) -> bool {
+        self.touched.contains(&pos)
+    }
+
+    // Puts back the cells of a saved chunk, ready to flow again.
+    pub fn insert_chunk(&mut self, pos: (i32, i32, i32), cells: HashMap<u16, Cell>) {
+        for (i, c) in cells.into_iter() {
+            let i = i as i32;
+            let (x, y, z) = ((pos.0 << 4) | (i >> 8), (pos.1 << 4) | ((i >> 4) & 15),
+                             (pos.2 << 4) | (i & 15));
+            self.cells.insert((x, y, z), c);
+            self.wake_around(x, y, z);
+            self.changed = true;
+        }
+    }
+
+    pub fn take_touched(&mut self) -> Vec<(i32, i32, i32)> {
+        self.touched.drain().collect()
+    }
+
+    pub fn wake_around(&mut self, x: i32, y: i32, z: i32) {
+        for &(dx, dy, dz) in delta::AROUND.iter() {
+            let p = (x + dx, y + dy, z + dz);
+            if self.cells.contains_key(&p) { self.changed = true; }
+            self.active.insert(p);
+        }
+    }
+
+    pub fn take_changed(&mut self) -> bool {
+        ::std::mem::replace(&mut self.changed, false)
+    }
+
+    // Cells are stepped in sorted order so the outcome does not depend on
+    // hash order.
+    pub fn tick(&mut self, world: &InfiniteWorld) {
+        self.ticks += 1;
+        let mut active: Vec<(i32, i32, i32)> = self.active.drain().collect();
+        active.sort();
+        for (x, y, z) in active.into_iter() {
+            let cell = match self.at(x, y, z) {
+                Some(c) => c,
+                None => continue,
+            };
+            if self.ticks % cell.fluid.pace() != 0 {
+                self.active.insert((x, y, z));
+                continue;
+            }
+            self.flow(x, y, z, cell, world);
+        }
+    }
+
+    // Fills the spot below first, then hands single levels to lower
+    // neighbours at the sides. Different fluids do not mix.
+    fn flow(&mut self, x: i32, y: i32, z: i32, cell: Cell, world: &InfiniteWorld) {
+        let mut level = cell.level;
+
+        if open(world, x, y - 1, z) {
+            let below = match self.at(x, y - 1, z) {
+                Some(c) if c.fluid == cell.fluid => Some(c.level),
+                Some(_) => None,
+                None => Some(0),
+            };
+            if let Some(below) = below {
+                let moved = (MAX_LEVEL - below).min(level);
+                if moved > 0 {
+                    level -= moved;
+                    self.set(x, y - 1, z, Some(Cell { fluid: cell.fluid, level: below + moved }));
+                }
+            }
+        }
+
+        for &(dx, _, dz) in SIDES.iter() {
+            if level <= 1 { break; }
+            let (sx, sz) = (x + dx, z + dz);
+            if !open(world, sx, y, sz) { continue; }
+            let side = match self.at(sx, y, sz) {
+                Some(c) if c.fluid == cell.fluid => c.level,
+                Some(_) => continue,
+                None => 0,
+            };
+            if level > side + 1 {
+                level -= 1;
+                self.set(sx, y, sz, Some(Cell { fluid: cell.fluid, level: side + 1 }));
+            }
+        }
+
+        if level != cell.level {
+            self.set(x, y, z, Some(Cell { fluid: cell.fluid, level }));
+        }
+    }
+
+    // Faces of each cell that are not against the same fluid or solid ground.
+    pub fn surface(&self, world: &InfiniteWorld, tile: [f32; 4]) -> Vec<Vertex> {
+        let mut vertices = Vec::new();
+        for (&(x, y, z), cell) in self.cells.iter() {
+            let height = cell.level as f32 / MAX_LEVEL as f32;
+            let same = |dx: i32, dy: i32, dz: i32| match self.at(x + dx, y + dy, z + dz) {
+                Some(c) if c.fluid == cell.fluid => Some(c.level as f32 / MAX_LEVEL as f32),
+                _ => None,
+            };
+            for f in 0..6 {
+                let d = cube::Face::from_usize(f).unwrap().direction();
+                let bottom = match (d[0], d[1], d[2]) {
+                    (0, 1, 0) => if same(0, 1, 0).is_some() { continue; } else { 0.0 },
+                    (0, -1, 0) => if same(0, -1, 0).is_some()
+                                  || !open(world, x, y - 1, z) { continue; } else { 0.0 },
+                    _ => {
+                        if !open(world, x + d[0], y, z + d[2]) { continue; }
+                        match same(d[0], 0, d[2]) {
+                            Some(h) if h >= height => continue,
+                            Some(h) => h,
+                            None => 0.0,
+                        }
+                    },
+                };
+                let (x, y, z) = (x as f32, y as f32, z as f32);
+                vertices.extend(world::box_face(f,
+                    [x, y + bottom, z], [x + 1.0, y + height, z + 1.0],
+                    tile, cell.fluid.color()));
+            }
+        }
+        vertices
+    }
+}
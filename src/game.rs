@@ -1,42 +1,124 @@
 use input::GenericEvent;
 
+use std::io;
+use std::path::Path;
+
 use world::*;
 use tool::*;
 use bag::*;
 use player::*;
 use controls::*;
+use save::Store;
+use shape::Shape;
+use gen;
+use material;
+use config::GenConfig;
 use rand::{self, Rng};
 
+const SAVE_DIR: &'static str = "world";
+const SPAWN: [f64; 3] = [0.0, 0.0, 3.0];
+
 pub struct Game {
     pub milieu: Milieu,
     pub tool: Tool,
-    //pub bag: Bag,
+    pub bag: Bag,
     pub player: Player,
     pub controls: PlayerController,
 }
 
 impl Game {
-    pub fn new() -> Game {
+    // The seed, generator and generator config only apply to a new world, a
+    // saved one keeps its own.
+    pub fn new(seed: Option<usize>, generator: Option<String>, gen_config: Option<String>)
+    -> Game {
+
+        let store = match Store::open(Path::new(SAVE_DIR)) {
+            Ok(store) => store,
+            Err(e) => panic!("Could not open world: {}", e),
+        };
+        let mut m = match store.read_level() {
+            Ok(Some((saved, name))) => {
+                if seed.map_or(false, |s| s != saved) {
+                    println!("Keeping the saved world's seed {}", saved);
+                }
+                if generator.as_ref().map_or(false, |g| *g != name) {
+                    println!("Keeping the saved world's generator {}", name);
+                }
+                if gen_config.is_some() {
+                    println!("Keeping the saved world's generator config");
+                }
+                // Worlds from before configs were saved use the defaults.
+                let config = match store.read_gen_config() {
+                    Ok(Some(text)) => GenConfig::parse(&text),
+                    Ok(None) => Ok(GenConfig::default()),
+                    Err(e) => Err(e),
+                };
+                match config {
+                    Ok(config) => Game::milieu(saved, &name, &config),
+                    Err(e) => panic!("Could not load world: {}", e),
+                }
+            },
+            Ok(None) => {
+                let config = match gen_config {
+                    Some(path) => match GenConfig::load(Path::new(&path)) {
+                        Ok(config) => config,
+                        Err(e) => panic!("Could not load generator config {}: {}", path, e),
+                    },
+                    None => GenConfig::default(),
+                };
+                let saved = config.to_text().and_then(|text| store.write_gen_config(&text));
+                if let Err(e) = saved {
+                    panic!("Could not save generator config: {}", e);
+                }
+                Game::dig_site(seed.unwrap_or_else(|| rand::thread_rng().gen()),
+                    generator.as_ref().map_or(gen::DEFAULT_GENERATOR, |g| g.as_str()), &config)
+            },
+            Err(e) => panic!("Could not load world: {}", e),
+        };
+        m.attach_store(store);
 
-        let mut rng = rand::thread_rng();
-        let mut m = Milieu::new_full(rng.gen::<usize>());
-        m.pull(1,0,0); //the first pulled block never actually gets pulled
-        for x in -6..6 { for y in 0..7 { for z in -6..6 {
-            m.pull(x,y,z);
-        }}}
+        println!("Seed {}, generator {}", m.seed(), m.generator());
 
         let p = Player::new(
-            [0.0, 0.0, 3.0],
+            SPAWN,
         );
+        m.maintain(p.camera().position);
+        // The chunks around spawn have to be in before the player can stand.
+        m.settle();
 
         Game {
             milieu: m,
             tool: Tool::new(),
+            bag: Bag::new(),
             player: p,
             controls: PlayerController::keyboard_wars(),
         }
     }
 
+    fn milieu(seed: usize, generator: &str, config: &GenConfig) -> Milieu {
+        match gen::by_name(generator, seed, config) {
+            Some(g) => Milieu::with_generator(seed, generator, g),
+            None => panic!("Unknown world generator {}, try one of {}",
+                           generator, gen::GENERATORS.join(", ")),
+        }
+    }
+
+    fn dig_site(seed: usize, generator: &str, config: &GenConfig) -> Milieu {
+        let mut m = Game::milieu(seed, generator, config);
+        m.clear(&Shape::cuboid([-6, 0, -6], [5, 6, 5]));
+        // Soil carries only a few blocks sideways, so beams hold the ceiling.
+        let beam = Block::new(material::BEAM, 0, material::get(material::BEAM).color);
+        for &(x, z) in [(-3, -3), (2, -3), (-3, 2), (2, 2)].iter() {
+            m.fill(&Shape::cuboid([x, 0, z], [x, 6, z]), &beam);
+        }
+        m.forget_history();
+        m
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        self.milieu.save()
+    }
+
     pub fn event<E: GenericEvent>(&mut self, e: &E) {
 
         e.mouse_relative(|dx, dy| {
@@ -44,10 +126,12 @@ impl Game {
         });
 
         e.press(|button| {
-            self.controls.input(button, true, &mut self.player, &mut self.tool);
+            self.controls.input(button, true, &mut self.player, &mut self.tool, &mut self.bag,
+                                &mut self.milieu);
         });
         e.release(|button| {
-            self.controls.input(button, false, &mut self.player, &mut self.tool);
+            self.controls.input(button, false, &mut self.player, &mut self.tool, &mut self.bag,
+                                &mut self.milieu);
         });
 
         e.update(|args| {
@@ -55,7 +139,13 @@ impl Game {
             let dt = args.dt as f32;
 
             self.player.update(dt, &mut self.milieu);
-            self.tool.update(dt, &mut self.milieu, &self.player);
+            self.tool.update(dt, &mut self.milieu, &self.player, &mut self.bag);
+            let damage = self.milieu.update_falling(dt, self.player.hitbox());
+            if damage > 0.0 && self.player.hurt(damage) {
+                self.player.respawn(SPAWN);
+            }
+            self.milieu.update_ticks(dt);
+            self.milieu.maintain(self.player.camera().position);
         });
     }
 }
\ No newline at end of file
@@ -1,27 +1,294 @@
-use super::Block;
-use rand::{self, Rng};
+use world::{Block, Spot};
+use material::{self, MaterialId};
 use noise::*;
+use fluid::Fluid;
+use flat::{Flat, Superflat};
+use config::{GenConfig, NoiseLayer, Deposit, RampStop};
+use structure::{Structure, Piece};
+use std::sync::Arc;
+
+pub const DEFAULT_GENERATOR: &'static str = "noise";
+pub const GENERATORS: [&'static str; 3] = ["noise", "flat", "superflat"];
+
+// What the world is made of before anyone digs into it. Generators are
+// shared with the job pool, and must answer from the seed and position alone
+// so a chunk can be rebuilt the same at any time.
+pub trait WorldGenerator: Send + Sync {
+    fn at(&self, x: i32, y: i32, z: i32) -> Block;
+    // Just the material at(), with caves counted as solid, for checks that
+    // need nothing else and should not pay for colors or cave noise.
+    fn material_at(&self, x: i32, y: i32, z: i32) -> MaterialId {
+        self.at(x, y, z).material_id()
+    }
+    // Natural air, left hidden until it is broken into.
+    fn is_cave(&self, _x: i32, _y: i32, _z: i32) -> bool {
+        false
+    }
+    // Fluid trapped in the ground here, released when the spot is dug out.
+    fn fluid_at(&self, _x: i32, _y: i32, _z: i32) -> Option<Fluid> {
+        None
+    }
+    // What the generator puts here: natural air in caves, otherwise at().
+    fn spot(&self, x: i32, y: i32, z: i32) -> Spot {
+        if self.is_cave(x, y, z) { Spot::Empty } else { Spot::Rich(self.at(x, y, z)) }
+    }
+}
+
+// The built in generators, as chosen when a world is created. Only the noise
+// generator is tuned by the config.
+pub fn by_name(name: &str, seed: usize, config: &GenConfig) -> Option<Arc<dyn WorldGenerator>> {
+    match name {
+        "noise" => Some(Arc::new(Gen::with_config(seed, config))),
+        "flat" => Some(Arc::new(Flat::new(material::STONE))),
+        "superflat" => Some(Arc::new(Superflat::standard())),
+        _ => None,
+    }
+}
+
+// Mixes a seed and a block position into well spread bits, so everything
+// generated at a position depends on nothing else.
+pub fn hash(seed: usize, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = seed as u64 ^ 0x9E37_79B9_7F4A_7C15;
+    for &v in [x, y, z].iter() {
+        h = (h ^ v as u32 as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h ^= h >> 31;
+    }
+    h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 29)
+}
+
+// Blocks across one geode at most, so a cell always has room for it.
+const GEODE_RADIUS: i32 = 5;
+
+// The config with material names looked up and noises built.
+struct Region {
+    below: f32,
+    soil: MaterialId,
+    loose: MaterialId,
+    sediment: MaterialId,
+    tint: [f32; 3],
+}
+
+struct Ore {
+    material: MaterialId,
+    deposit: Deposit,
+    scale: f32,
+    top: i32,
+    depth: i32,
+    size: f32,
+    noise: Billow<f32>,
+}
+
+fn id(name: &str) -> MaterialId {
+    material::by_name(name).expect("generator config names an unknown material").id
+}
+
+fn billow(seed: usize, layer: &NoiseLayer) -> Billow<f32> {
+    Billow::new().set_seed(seed.wrapping_add(layer.seed)).set_octaves(layer.octaves)
+}
+
+fn fbm(seed: usize, layer: &NoiseLayer) -> Fbm<f32> {
+    Fbm::new().set_seed(seed.wrapping_add(layer.seed)).set_octaves(layer.octaves)
+}
+
+// The cell of size blocks that v falls in, rounding down.
+fn cell(v: i32, size: i32) -> i32 {
+    if v < 0 { (v + 1) / size - 1 } else { v / size }
+}
+
+fn scaled(layer: &NoiseLayer, x: f32, y: f32, z: f32) -> [f32; 3] {
+    [x / layer.scale, y / layer.scale, z / layer.scale]
+}
 
 pub struct Gen {
+    seed: usize,
+    config: GenConfig,
+    ramp: Vec<RampStop>,
+    regions: Vec<Region>,
+    ores: Vec<Ore>,
+    bedrock: MaterialId,
+    magma: MaterialId,
     red: Billow<f32>,
     green: Billow<f32>,
     blue: Billow<f32>,
+    wet: Billow<f32>,
+    tunnels: (Billow<f32>, Billow<f32>),
+    caverns: Fbm<f32>,
+    wobble: Billow<f32>,
+    region_noise: Fbm<f32>,
+    loose: Billow<f32>,
 }
 
 impl Gen {
     pub fn new(seed: usize) -> Gen {
-        let red = Billow::new().set_seed(seed+0);
-        let green = Billow::new().set_seed(seed+1);
-        let blue = Billow::new().set_seed(seed+2);
-        Gen {red, green, blue}
-    }
-    pub fn at(&self, x: i32, y: i32, z: i32) -> Block {
-        let (x,y,z) = (x as f32 / 100.0, y as f32 / 100.0, z as f32 / 100.0);
-        let color =  [self.red.get([x,y,z]) + 0.8,
-                    self.green.get([x,y,z]) + 0.8,
-                     self.blue.get([x,y,z]) + 0.8,
-                     1.0];
-        let mut rng = rand::thread_rng();
-        Block::new(rng.gen::<usize>(), color)
-    }
-}
\ No newline at end of file
+        Gen::with_config(seed, &GenConfig::default())
+    }
+    pub fn with_config(seed: usize, config: &GenConfig) -> Gen {
+        let c = config;
+        let mut ramp = c.ramp.clone();
+        ramp.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap_or(::std::cmp::Ordering::Equal));
+        let regions = c.regions.iter().map(|r| Region {
+            below: r.below,
+            soil: id(&r.soil),
+            loose: id(&r.loose),
+            sediment: id(&r.sediment),
+            tint: r.tint,
+        }).collect();
+        let ores = c.ores.iter().map(|o| Ore {
+            material: id(&o.material),
+            deposit: o.deposit,
+            scale: o.noise.scale,
+            top: o.top,
+            depth: o.depth,
+            size: o.size,
+            noise: billow(seed, &o.noise),
+        }).collect();
+        let mut second = c.tunnels.clone();
+        second.seed = second.seed.wrapping_add(1);
+        Gen {
+            seed,
+            config: c.clone(),
+            ramp,
+            regions,
+            ores,
+            bedrock: id(&c.strata.bedrock_material),
+            magma: id(&c.strata.magma_material),
+            red: billow(seed, &c.color),
+            green: billow(seed.wrapping_add(1), &c.color),
+            blue: billow(seed.wrapping_add(2), &c.color),
+            wet: billow(seed, &c.wet),
+            tunnels: (billow(seed, &c.tunnels), billow(seed, &second)),
+            caverns: fbm(seed, &c.caverns),
+            wobble: billow(seed, &c.strata.wobble_noise),
+            region_noise: fbm(seed, &c.region_noise),
+            loose: billow(seed, &c.loose),
+        }
+    }
+    fn region(&self, x: i32, z: i32) -> &Region {
+        let p = scaled(&self.config.region_noise, x as f32, 0.0, z as f32);
+        let n = self.region_noise.get(p);
+        self.regions.iter().find(|r| n < r.below)
+            .unwrap_or(&self.regions[self.regions.len() - 1])
+    }
+    // Bands of rock by depth, from the top down: soil, sediment, bedrock and
+    // below that the magma zone. Band boundaries wander up and down so they
+    // are not flat.
+    fn stratum(&self, x: i32, y: i32, z: i32, region: &Region) -> MaterialId {
+        let strata = &self.config.strata;
+        let depth = y as f32 + strata.wobble
+            * self.wobble.get(scaled(&strata.wobble_noise, x as f32, 0.0, z as f32));
+        if depth > strata.soil as f32 {
+            let p = scaled(&self.config.loose, x as f32, y as f32, z as f32);
+            if self.loose.get(p) > self.config.loose_level { region.loose } else { region.soil }
+        }
+        else if depth > strata.sediment as f32 { region.sediment }
+        else if depth > strata.bedrock as f32 { self.bedrock }
+        else { self.magma }
+    }
+    // The ramp color at height y, blended between the stops around it.
+    fn shade(&self, y: f32) -> [f32; 3] {
+        let ramp = &self.ramp;
+        if y <= ramp[0].y { return ramp[0].color; }
+        for w in ramp.windows(2) {
+            let (a, b) = (&w[0], &w[1]);
+            if y <= b.y {
+                let t = (y - a.y) / (b.y - a.y);
+                return [a.color[0] + (b.color[0] - a.color[0]) * t,
+                        a.color[1] + (b.color[1] - a.color[1]) * t,
+                        a.color[2] + (b.color[2] - a.color[2]) * t];
+            }
+        }
+        ramp[ramp.len() - 1].color
+    }
+    // Some(true) inside a geode, Some(false) in its crystal lining. Each geode
+    // sits wholly inside its cell, so only the cell of the spot is checked.
+    fn geode_at(&self, x: i32, y: i32, z: i32) -> Option<bool> {
+        let geodes = &self.config.geodes;
+        let size = geodes.cell;
+        let (cx, cy, cz) = (cell(x, size), cell(y, size), cell(z, size));
+        if (cy + 1) * size > geodes.depth { return None; }
+        let h = hash(self.seed.wrapping_add(1), cx, cy, cz);
+        if h % geodes.odds != 0 { return None; }
+        let radius = GEODE_RADIUS - 2 + ((h >> 8) % 3) as i32;
+        let room = (size - 2 * radius) as u64;
+        let centre = |c: i32, shift: u32| c * size + radius + ((h >> shift) % room) as i32;
+        let (dx, dy, dz) = (x - centre(cx, 16), y - centre(cy, 32), z - centre(cz, 48));
+        let d = dx * dx + dy * dy + dz * dz;
+        if d < (radius - 1) * (radius - 1) { Some(true) }
+        else if d < radius * radius { Some(false) }
+        else { None }
+    }
+    // Structures keep to their cells, so only the spot's own cell can have
+    // one here, and every chunk sees the same structure whichever asks first.
+    fn structure_at(&self, x: i32, y: i32, z: i32) -> Option<Piece> {
+        let s = &self.config.structures;
+        let c = (cell(x, s.cell), cell(y, s.cell), cell(z, s.cell));
+        Structure::plan(self.seed.wrapping_add(2), c, s.cell, s.odds)
+            .and_then(|st| st.piece(x, y, z))
+    }
+    // Ores grow richer from nothing at their top to their full size a
+    // depth below it.
+    pub fn ore_at(&self, x: i32, y: i32, z: i32) -> Option<MaterialId> {
+        for ore in self.ores.iter() {
+            if y > ore.top { continue; }
+            let richness = ((ore.top - y) as f32 / ore.depth as f32).min(1.0);
+            let p = [x as f32 / ore.scale, y as f32 / ore.scale, z as f32 / ore.scale];
+            // Billow ranges over about -1 to 1, creases at the bottom.
+            let found = match ore.deposit {
+                Deposit::Vein => ore.noise.get(p) < -1.0 + 2.0 * ore.size * richness,
+                Deposit::Cluster => ore.noise.get(p) > 1.0 - 2.0 * ore.size * richness,
+            };
+            if found { return Some(ore.material); }
+        }
+        None
+    }
+}
+
+impl WorldGenerator for Gen {
+    fn at(&self, x: i32, y: i32, z: i32) -> Block {
+        let h = hash(self.seed, x, y, z) as usize;
+        if let Some(Piece::Solid(m)) = self.structure_at(x, y, z) {
+            return Block::new(m, h, material::get(m).color);
+        }
+        if let Some(false) = self.geode_at(x, y, z) {
+            return Block::new(material::CRYSTAL, h, material::get(material::CRYSTAL).color);
+        }
+        if let Some(ore) = self.ore_at(x, y, z) {
+            return Block::new(ore, h, material::get(ore).color);
+        }
+        let region = self.region(x, z);
+        let rock = self.stratum(x, y, z, region);
+        let base = material::get(rock).color;
+        let shade = self.shade(y as f32);
+        let speckle = self.config.speckle;
+        let p = scaled(&self.config.color, x as f32, y as f32, z as f32);
+        let tone = |i: usize, noise: &Billow<f32>|
+            base[i] * region.tint[i] * shade[i] * (1.0 + speckle * noise.get(p));
+        let color = [tone(0, &self.red), tone(1, &self.green), tone(2, &self.blue), 1.0];
+        Block::new(rock, h, color)
+    }
+    fn material_at(&self, x: i32, y: i32, z: i32) -> MaterialId {
+        if let Some(Piece::Solid(m)) = self.structure_at(x, y, z) { return m; }
+        if let Some(false) = self.geode_at(x, y, z) { return material::CRYSTAL; }
+        if let Some(ore) = self.ore_at(x, y, z) { return ore; }
+        self.stratum(x, y, z, self.region(x, z))
+    }
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        if let Some(piece) = self.structure_at(x, y, z) { return piece == Piece::Air; }
+        if let Some(inside) = self.geode_at(x, y, z) { return inside; }
+        let c = &self.config;
+        let p = scaled(&c.tunnels, x as f32, y as f32, z as f32);
+        if self.tunnels.0.get(p) < -1.0 + 2.0 * c.tunnel_width
+            && self.tunnels.1.get(p) < -1.0 + 2.0 * c.tunnel_width { return true; }
+        // Flattened so caverns are wider than they are tall.
+        y < c.cavern_depth && self.caverns.get(scaled(&c.caverns,
+            x as f32, y as f32 * 2.0, z as f32)) > c.cavern_level
+    }
+    fn fluid_at(&self, x: i32, y: i32, z: i32) -> Option<Fluid> {
+        let c = &self.config;
+        // Pockets in the magma zone are more common than above it.
+        let level = if y < c.strata.bedrock { c.magma_wet_level } else { c.wet_level };
+        if self.wet.get(scaled(&c.wet, x as f32, y as f32, z as f32)) < level { return None; }
+        if y < c.lava_depth { Some(Fluid::Lava) } else { Some(Fluid::Water) }
+    }
+}
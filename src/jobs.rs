@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+
+use world::{Chunk, InfiniteWorld, Vertex};
+use mesh::Quad;
+use delta::Delta;
+use gen::WorldGenerator;
+use material::Tiles;
+
+const WORKERS: usize = 4;
+
+// Work handed to the pool. Each job carries its own snapshot of what it reads,
+// so workers never touch the live world.
+pub enum Job {
+    Generate {
+        pos: (i32, i32, i32),
+        gen: Arc<dyn WorldGenerator>,
+        delta: Delta,
+    },
+    Mesh {
+        pos: (i32, i32, i32),
+        world: InfiniteWorld,
+        tiles: Arc<Tiles>,
+        versions: Vec<Option<u64>>,
+    },
+    // Merges the faces a chunk holds into the vertices drawn for it.
+    Surface {
+        pos: (i32, i32, i32),
+        chunk: Arc<Chunk>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Generate,
+    Mesh,
+    Surface,
+}
+
+pub enum Done {
+    Generated(Chunk),
+    Meshed {
+        pos: (i32, i32, i32),
+        meshes: HashMap<u16, Vec<Quad>>,
+        versions: Vec<Option<u64>>,
+    },
+    Surfaced {
+        pos: (i32, i32, i32),
+        vertices: Vec<Vertex>,
+    },
+    // The job panicked. Sent so nothing waits for it forever.
+    Failed {
+        pos: (i32, i32, i32),
+        kind: Kind,
+    },
+}
+
+impl Job {
+    fn describe(&self) -> ((i32, i32, i32), Kind) {
+        match *self {
+            Job::Generate { pos, .. } => (pos, Kind::Generate),
+            Job::Mesh { pos, .. } => (pos, Kind::Mesh),
+            Job::Surface { pos, .. } => (pos, Kind::Surface),
+        }
+    }
+
+    fn run(self) -> Done {
+        match self {
+            Job::Generate { pos, gen, delta } => {
+                Done::Generated(Chunk::generate(pos.0, pos.1, pos.2, &*gen, &delta))
+            },
+            Job::Mesh { pos, world, tiles, versions } => {
+                Done::Meshed { pos, meshes: world.mesh_chunk(pos.0, pos.1, pos.2, &tiles), versions }
+            },
+            Job::Surface { pos, chunk } => {
+                Done::Surfaced { pos, vertices: chunk.build_surface() }
+            },
+        }
+    }
+}
+
+pub struct Pool {
+    jobs: Option<Sender<Job>>,
+    done: Receiver<Done>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    pub fn new() -> Pool {
+        let (jobs, queue) = channel::<Job>();
+        let (results, done) = channel();
+        let queue = Arc::new(Mutex::new(queue));
+
+        let workers = (0..WORKERS).map(|_| {
+            let queue = queue.clone();
+            let results = results.clone();
+            thread::spawn(move || loop {
+                let job = match queue.lock() {
+                    Ok(q) => q.recv(),
+                    Err(_) => return,
+                };
+                let job = match job {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                let (pos, kind) = job.describe();
+                let done = match panic::catch_unwind(AssertUnwindSafe(|| job.run())) {
+                    Ok(done) => done,
+                    Err(_) => Done::Failed { pos, kind },
+                };
+                if results.send(done).is_err() { return; }
+            })
+        }).collect();
+
+        Pool {
+            jobs: Some(jobs),
+            done,
+            workers,
+        }
+    }
+
+    pub fn submit(&self, job: Job) {
+        if let Some(ref jobs) = self.jobs {
+            jobs.send(job).expect("job pool has shut down");
+        }
+    }
+
+    pub fn try_next(&self) -> Option<Done> {
+        self.done.try_recv().ok()
+    }
+
+    pub fn next(&self) -> Option<Done> {
+        self.done.recv().ok()
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use world::Spot;
+
+const JOURNAL_LIMIT: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub pos: (i32, i32, i32),
+    pub before: Spot,
+    pub after: Spot,
+    // Dug out by the player, who got the block's drops for it.
+    pub mined: bool,
+}
+
+// Changes are kept in groups so a whole mining stroke or bulk edit can be
+// undone in one step. Anything recorded outside begin()/end() is its own group.
+pub struct Journal {
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    open: Vec<Change>,
+    depth: usize,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn begin(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn end(&mut self) {
+        if self.depth == 0 { return; }
+        self.depth -= 1;
+        if self.depth == 0 && !self.open.is_empty() {
+            let group = ::std::mem::replace(&mut self.open, Vec::new());
+            self.push(group);
+        }
+    }
+
+    pub fn record(&mut self, change: Change) {
+        self.redo.clear();
+        if self.depth > 0 {
+            self.open.push(change);
+        } else {
+            self.push(vec![change]);
+        }
+    }
+
+    fn push(&mut self, group: Vec<Change>) {
+        self.undo.push_back(group);
+        if self.undo.len() > JOURNAL_LIMIT {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn take_undo(&mut self) -> Option<Vec<Change>> {
+        let group = self.undo.pop_back();
+        if let Some(ref g) = group { self.redo.push(g.clone()); }
+        group
+    }
+
+    pub fn take_redo(&mut self) -> Option<Vec<Change>> {
+        let group = self.redo.pop();
+        if let Some(ref g) = group { self.push(g.clone()); }
+        group
+    }
+
+    // Whether any change kept, to undo or redo, lies in the chunk.
+    pub fn touches(&self, chunk: (i32, i32, i32)) -> bool {
+        let inside = |c: &Change| (c.pos.0 >> 4, c.pos.1 >> 4, c.pos.2 >> 4) == chunk;
+        self.undo.iter().chain(self.redo.iter()).any(|g| g.iter().any(|c| inside(c)))
+            || self.open.iter().any(|c| inside(c))
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open.clear();
+        self.depth = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(x: i32) -> Change {
+        Change { pos: (x, 0, 0), before: Spot::Full, after: Spot::Empty, mined: true }
+    }
+
+    fn xs(group: Option<Vec<Change>>) -> Vec<i32> {
+        group.unwrap().iter().map(|c| c.pos.0).collect()
+    }
+
+    #[test]
+    fn undo_redo_order() {
+        let mut j = Journal::new();
+        j.record(change(1));
+        j.begin();
+        j.record(change(2));
+        j.begin();
+        j.record(change(3));
+        j.end();
+        // Still open until the outer end.
+        assert_eq!(xs(j.take_undo()), vec![1]);
+        j.end();
+        j.record(change(4));
+
+        assert_eq!(xs(j.take_undo()), vec![4]);
+        assert_eq!(xs(j.take_undo()), vec![2, 3]);
+        assert!(j.take_undo().is_none());
+        assert_eq!(xs(j.take_redo()), vec![2, 3]);
+        assert_eq!(xs(j.take_redo()), vec![4]);
+        assert!(j.take_redo().is_none());
+        assert_eq!(xs(j.take_undo()), vec![4]);
+    }
+
+    #[test]
+    fn record_drops_redo() {
+        let mut j = Journal::new();
+        j.record(change(1));
+        j.record(change(2));
+        j.take_undo();
+        j.record(change(3));
+        assert!(j.take_redo().is_none());
+        assert_eq!(xs(j.take_undo()), vec![3]);
+        assert_eq!(xs(j.take_undo()), vec![1]);
+    }
+
+    #[test]
+    fn limit_and_touches() {
+        let mut j = Journal::new();
+        for x in 0..JOURNAL_LIMIT as i32 + 16 {
+            j.record(change(x * 16));
+        }
+        // The oldest groups went first.
+        assert!(!j.touches((0, 0, 0)));
+        assert!(!j.touches((15, 0, 0)));
+        assert!(j.touches((16, 0, 0)));
+        let mut n = 0;
+        while j.take_undo().is_some() { n += 1; }
+        assert_eq!(n, JOURNAL_LIMIT);
+        // Undone groups still count until they are cleared.
+        assert!(j.touches((16, 0, 0)));
+        j.clear();
+        assert!(!j.touches((16, 0, 0)));
+        assert!(j.take_redo().is_none());
+    }
+}
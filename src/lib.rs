@@ -0,0 +1,37 @@
+extern crate vecmath;
+extern crate camera_controllers;
+#[macro_use]
+extern crate gfx;
+extern crate gfx_voxel;
+extern crate rand;
+extern crate input;
+extern crate noise;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+pub mod game;
+pub mod player;
+pub mod world;
+pub mod controls;
+pub mod bag;
+pub mod tool;
+pub mod gen;
+pub mod save;
+pub mod material;
+pub mod delta;
+pub mod journal;
+pub mod shape;
+pub mod schematic;
+pub mod jobs;
+pub mod falling;
+pub mod fluid;
+pub mod tick;
+pub mod support;
+pub mod events;
+pub mod flat;
+pub mod config;
+pub mod structure;
+pub mod mesh;
+pub mod cli;
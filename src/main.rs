@@ -1,3 +1,4 @@
+extern crate carvel;
 extern crate piston_window;
 extern crate vecmath;
 extern crate camera_controllers;
@@ -8,26 +9,12 @@ extern crate gfx_text;
 extern crate gfx_debug_draw;
 extern crate shader_version;
 extern crate find_folder;
-extern crate rand;
-extern crate input;
-extern crate line_drawing;
-extern crate noise;
 extern crate fps_counter;
 
-mod game;
-use game::*;
-
-mod player;
-
-mod world;
-use world::{
-    Vertex, Block, Spot
-};
-
-mod controls;
-mod bag;
-mod tool;
-mod gen;
+use carvel::game::*;
+use carvel::world::Vertex;
+use carvel::material;
+use carvel::cli::arg;
 
 use piston_window::*;
 use gfx::traits::*;
@@ -55,6 +42,10 @@ gfx_pipeline!( pipe {
 
 fn main() {
 
+    let seed = arg("seed").map(|s| s.parse().expect("--seed needs a number"));
+    let generator = arg("generator");
+    let gen_config = arg("gen-config");
+
     let loadstatus = |window: &mut PistonWindow, glyphs: &mut Glyphs, message: &str| {
         if let Some(e) = window.next() {
             window.draw_2d(&e, |c, g| {
@@ -102,9 +93,17 @@ fn main() {
     let draw_state = piston_window::DrawState::new_alpha();
 
     let mut atlas = texture::AtlasBuilder::new(assets.join("blocks"), 256, 256);
-    let _offset = atlas.load("ground");
+    let offsets: Vec<_> = material::all().iter()
+        .map(|m| (m.id, atlas.load(m.texture)))
+        .collect();
     let texture = atlas.complete(factory);
 
+    let (tw, th) = (texture.width as f32, texture.height as f32);
+    let mut tiles = material::Tiles::new();
+    for (id, (u, v)) in offsets.into_iter() {
+        tiles.set(id, [u as f32 / tw, v as f32 / th, 256.0 / tw, 256.0 / th]);
+    }
+
     let sinfo = gfx::texture::SamplerInfo::new(
         gfx::texture::FilterMethod::Bilinear,
         gfx::texture::WrapMode::Clamp);
@@ -147,7 +146,8 @@ fn main() {
 
     loadstatus(&mut window, &mut glyphs, "Loading World");
 
-    let mut game = Game::new();
+    let mut game = Game::new(seed, generator, gen_config);
+    game.milieu.set_tiles(tiles);
 
 
     while let Some(e) = window.next() {
@@ -179,6 +179,13 @@ fn main() {
                 c.transform.trans(5.0, 20.0),
                 g
             ).unwrap();
+            text::Text::new_color([1.0, 0.2, 0.2, 1.0], 18).draw(
+                &format!("{:.0} hp", game.player.health.max(0.0)),
+                &mut glyphs,
+                &c.draw_state,
+                c.transform.trans(5.0, 120.0),
+                g
+            ).unwrap();
             for row in 0..3 {
             for column in 0..3 {
                 text::Text::new_color([1.0, 1.0, 1.0, 1.0], 14).draw(
@@ -200,4 +207,8 @@ fn main() {
                         (window.draw_size().height/2 - crosshair.get_size().1 / 2) as f64);
         }
     }
+
+    if let Err(e) = game.save() {
+        println!("Could not save world: {}", e);
+    }
 }
@@ -0,0 +1,133 @@
+pub type MaterialId = u16;
+
+pub const STONE:  MaterialId = 0;
+pub const DIRT:   MaterialId = 1;
+pub const GRAVEL: MaterialId = 2;
+pub const COAL:   MaterialId = 3;
+pub const COPPER: MaterialId = 4;
+pub const IRON:   MaterialId = 5;
+pub const GOLD:   MaterialId = 6;
+pub const BRICK:  MaterialId = 7;
+pub const BEAM:   MaterialId = 8;
+pub const CRYSTAL: MaterialId = 9;
+pub const SAND:   MaterialId = 10;
+pub const SANDSTONE: MaterialId = 11;
+pub const SLATE:  MaterialId = 12;
+pub const BASALT: MaterialId = 13;
+pub const MAGMA:  MaterialId = 14;
+pub const RAIL:   MaterialId = 15;
+
+pub enum Drop {
+    Nothing,
+    Itself,
+    Other(MaterialId),
+}
+
+pub struct Material {
+    pub id: MaterialId,
+    pub name: &'static str,
+    pub texture: &'static str,
+    pub color: [f32; 4],
+    pub hardness: f32,
+    pub drop: Drop,
+    // Falls when the block below it is removed.
+    pub falls: bool,
+    // How many blocks sideways this can hold up without anything beneath.
+    pub span: i32,
+}
+
+impl Material {
+    pub fn drops(&self) -> Option<MaterialId> {
+        match self.drop {
+            Drop::Nothing => None,
+            Drop::Itself => Some(self.id),
+            Drop::Other(id) => Some(id),
+        }
+    }
+}
+
+static MATERIALS: [Material; 16] = [
+    Material { id: STONE,  name: "stone",  texture: "ground",
+               color: [1.0, 1.0, 1.0, 1.0],  hardness: 1.0, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: DIRT,   name: "dirt",   texture: "dirt2",
+               color: [0.6, 0.45, 0.3, 1.0], hardness: 0.5, drop: Drop::Itself,
+               falls: false, span: 2 },
+    Material { id: GRAVEL, name: "gravel", texture: "Gravel_Bed",
+               color: [0.7, 0.7, 0.7, 1.0],  hardness: 0.6, drop: Drop::Itself,
+               falls: true, span: 0 },
+    Material { id: COAL,   name: "coal",   texture: "ground",
+               color: [0.2, 0.2, 0.2, 1.0],  hardness: 1.5, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: COPPER, name: "copper", texture: "ground",
+               color: [0.8, 0.5, 0.3, 1.0],  hardness: 2.0, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: IRON,   name: "iron",   texture: "ground",
+               color: [0.75, 0.6, 0.55, 1.0], hardness: 2.5, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: GOLD,   name: "gold",   texture: "ground",
+               color: [1.0, 0.85, 0.3, 1.0], hardness: 3.0, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: BRICK,  name: "brick",  texture: "Old_Stone_Road",
+               color: [1.0, 1.0, 1.0, 1.0],  hardness: 1.2, drop: Drop::Other(GRAVEL),
+               falls: false, span: 5 },
+    Material { id: BEAM,   name: "beam",   texture: "Old_Stone_Road",
+               color: [0.6, 0.42, 0.25, 1.0], hardness: 0.8, drop: Drop::Itself,
+               falls: false, span: 12 },
+    Material { id: CRYSTAL, name: "crystal", texture: "ground",
+               color: [0.7, 0.45, 0.95, 1.0], hardness: 2.0, drop: Drop::Itself,
+               falls: false, span: 6 },
+    Material { id: SAND,   name: "sand",   texture: "Gravel_Bed",
+               color: [0.95, 0.85, 0.55, 1.0], hardness: 0.4, drop: Drop::Itself,
+               falls: true, span: 0 },
+    Material { id: SANDSTONE, name: "sandstone", texture: "Old_Stone_Road",
+               color: [0.9, 0.75, 0.5, 1.0], hardness: 0.9, drop: Drop::Other(SAND),
+               falls: false, span: 4 },
+    Material { id: SLATE,  name: "slate",  texture: "ground",
+               color: [0.55, 0.6, 0.7, 1.0], hardness: 1.3, drop: Drop::Itself,
+               falls: false, span: 7 },
+    Material { id: BASALT, name: "basalt", texture: "ground",
+               color: [0.35, 0.33, 0.35, 1.0], hardness: 2.0, drop: Drop::Itself,
+               falls: false, span: 8 },
+    Material { id: MAGMA,  name: "magma",  texture: "ground",
+               color: [0.7, 0.25, 0.1, 1.0], hardness: 2.5, drop: Drop::Other(BASALT),
+               falls: false, span: 4 },
+    Material { id: RAIL,   name: "rail",   texture: "Old_Stone_Road",
+               color: [0.5, 0.45, 0.42, 1.0], hardness: 1.5, drop: Drop::Itself,
+               falls: false, span: 6 },
+];
+
+pub fn all() -> &'static [Material] {
+    &MATERIALS
+}
+
+pub fn exists(id: MaterialId) -> bool {
+    (id as usize) < MATERIALS.len()
+}
+
+pub fn get(id: MaterialId) -> &'static Material {
+    &MATERIALS[id as usize]
+}
+
+pub fn by_name(name: &str) -> Option<&'static Material> {
+    MATERIALS.iter().find(|m| m.name == name)
+}
+
+// Where each material's texture sits in the block atlas, as [u, v, width, height].
+pub struct Tiles {
+    uv: Vec<[f32; 4]>,
+}
+
+impl Tiles {
+    pub fn new() -> Tiles {
+        Tiles {
+            uv: vec![[0.0, 0.0, 1.0, 1.0]; MATERIALS.len()],
+        }
+    }
+    pub fn set(&mut self, id: MaterialId, uv: [f32; 4]) {
+        self.uv[id as usize] = uv;
+    }
+    pub fn get(&self, id: MaterialId) -> [f32; 4] {
+        self.uv[id as usize]
+    }
+}
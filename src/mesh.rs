@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+
+use gfx_voxel::cube;
+use material::MaterialId;
+use world::{Vertex, CUBE_VERTICES, TRANS};
+
+// One exposed block face. Chunks keep these per spot, so an edit only redoes
+// the faces of the spots it touched, and merge them when building a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quad {
+    pub face: usize,
+    pub pos: [i32; 3],
+    pub material: MaterialId,
+    pub tile: [f32; 4],
+    pub color: [f32; 4],
+    pub textrans: u8,
+    pub light: [f32; 4],
+}
+
+// The axis a face looks along, then the two it spans.
+fn axes(face: usize) -> (usize, usize, usize) {
+    let d = cube::Face::from_usize(face).unwrap().direction();
+    if d[0] != 0 { (0, 1, 2) } else if d[1] != 0 { (1, 0, 2) } else { (2, 0, 1) }
+}
+
+impl Quad {
+    // Lit the same at every corner, so it can be stretched without the
+    // shading changing.
+    fn is_even(&self) -> bool {
+        self.light.iter().all(|&l| l == self.light[0])
+    }
+    // Only faces that would look the same drawn apart are merged.
+    fn joins(&self, other: &Quad) -> bool {
+        self.is_even() && other.is_even() && self.light == other.light
+            && self.material == other.material && self.tile == other.tile
+            && self.color == other.color && self.textrans == other.textrans
+    }
+    // The face stretched over size blocks from pos.
+    pub fn vertices(&self, size: [i32; 3]) -> Vec<Vertex> {
+        let (_, a, b) = axes(self.face);
+        let c: Vec<[i32; 3]> = cube::QUADS[self.face].iter().map(|&i| CUBE_VERTICES[i]).collect();
+        let t = TRANS[self.textrans as usize];
+        // Texture flips and turns swap which side u and v run along.
+        let runs = |tex: [i32; 4]| {
+            if (0..4).all(|i| (tex[i] == tex[0]) == (c[i][a] == c[0][a])) { size[a] } else { size[b] }
+        };
+        let (su, sv) = (runs(t[0]), runs(t[1]));
+        let mut vertices: Vec<Vertex> = (0..4).map(|i| {
+            Vertex::new(
+                [(self.pos[0] + c[i][0] * size[0]) as f32,
+                 (self.pos[1] + c[i][1] * size[1]) as f32,
+                 (self.pos[2] + c[i][2] * size[2]) as f32],
+                [(t[0][i] * su) as f32, (t[1][i] * sv) as f32],
+                self.tile,
+                self.color,
+                self.light[i])
+        }).collect();
+        // Quads are split along the 0-2 diagonal. Splitting along the one
+        // that joins the darker corners keeps the shading from streaking
+        // one way across the face.
+        let l = self.light;
+        if l[0] + l[2] > l[1] + l[3] {
+            vertices.rotate_left(1);
+        }
+        vertices
+    }
+}
+
+// One vertex quad per face.
+pub fn naive<'a, I>(quads: I) -> Vec<Vertex> where I: Iterator<Item = &'a Quad> {
+    let mut vertices = Vec::new();
+    for q in quads {
+        vertices.extend(q.vertices([1, 1, 1]));
+    }
+    vertices
+}
+
+// Merges neighbouring faces that are alike in everything into rectangles.
+// Faces that are shaded unevenly stay on their own.
+pub fn greedy<'a, I>(quads: I) -> Vec<Vertex> where I: Iterator<Item = &'a Quad> {
+    let mut layers: HashMap<(usize, i32), HashMap<(i32, i32), &Quad>> = HashMap::new();
+    for q in quads {
+        let (n, a, b) = axes(q.face);
+        layers.entry((q.face, q.pos[n])).or_insert_with(HashMap::new).insert((q.pos[a], q.pos[b]), q);
+    }
+
+    // In a fixed order, so the same faces always give the same mesh.
+    let mut keys: Vec<(usize, i32)> = layers.keys().cloned().collect();
+    keys.sort();
+    let mut vertices = Vec::new();
+    for &(face, depth) in keys.iter() {
+        let layer = &layers[&(face, depth)];
+        let (n, a, b) = axes(face);
+        let mut cells: Vec<(i32, i32)> = layer.keys().cloned().collect();
+        cells.sort();
+        let mut done = HashSet::new();
+        for &(u, v) in cells.iter() {
+            if done.contains(&(u, v)) { continue; }
+            let q = layer[&(u, v)];
+            // Grow along b first, then along a while the whole strip joins.
+            let (w, h) = {
+                let joins = |c: (i32, i32)| !done.contains(&c)
+                    && layer.get(&c).map_or(false, |o| q.joins(o));
+                let mut h = 1;
+                while joins((u, v + h)) { h += 1; }
+                let mut w = 1;
+                while (0..h).all(|k| joins((u + w, v + k))) { w += 1; }
+                (w, h)
+            };
+            for i in 0..w { for k in 0..h {
+                done.insert((u + i, v + k));
+            }}
+
+            let mut size = [0; 3];
+            size[n] = 1;
+            size[a] = w;
+            size[b] = h;
+            vertices.extend(q.vertices(size));
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material;
+
+    // A wall of up facing faces at y = 0, material and light by position.
+    fn wall<F>(pick: F) -> Vec<Quad> where F: Fn(i32, i32) -> (MaterialId, [f32; 4]) {
+        let mut quads = Vec::new();
+        for x in 0..16 { for z in 0..16 {
+            let (material, light) = pick(x, z);
+            quads.push(Quad {
+                face: 1,
+                pos: [x, 0, z],
+                material,
+                tile: [material as f32 / 16.0, 0.0, 1.0 / 16.0, 1.0],
+                color: [1.0; 4],
+                textrans: 0,
+                light,
+            });
+        }}
+        quads
+    }
+
+    // Summed area of the quads, each made of four vertices on a y plane.
+    fn area(vertices: &[Vertex]) -> f32 {
+        vertices.chunks(4).map(|q| {
+            let xs = q.iter().map(|v| v.a_pos[0]);
+            let zs = q.iter().map(|v| v.a_pos[2]);
+            let (x0, x1) = xs.fold((1e9f32, -1e9f32), |(lo, hi), x| (lo.min(x), hi.max(x)));
+            let (z0, z1) = zs.fold((1e9f32, -1e9f32), |(lo, hi), z| (lo.min(z), hi.max(z)));
+            (x1 - x0) * (z1 - z0)
+        }).sum()
+    }
+
+    fn compare(quads: &[Quad]) -> (usize, usize) {
+        let naive = naive(quads.iter());
+        let greedy = greedy(quads.iter());
+        assert_eq!(area(&naive), area(&greedy));
+        assert_eq!(area(&naive), quads.len() as f32);
+        (naive.len(), greedy.len())
+    }
+
+    #[test]
+    fn flat_wall() {
+        let quads = wall(|_, _| (material::STONE, [0.8; 4]));
+        let (naive, greedy) = compare(&quads);
+        assert_eq!(naive, 16 * 16 * 4);
+        assert_eq!(greedy, 4);
+    }
+
+    #[test]
+    fn two_materials() {
+        let quads = wall(|x, _| (if x < 6 { material::STONE } else { material::DIRT }, [0.8; 4]));
+        let (naive, greedy) = compare(&quads);
+        assert!(greedy < naive);
+        assert_eq!(greedy, 2 * 4);
+    }
+
+    #[test]
+    fn uneven_light() {
+        // Darkened along one edge, as beside a wall. Those faces stay single.
+        let quads = wall(|x, _| {
+            let light = if x == 0 { [0.5, 0.8, 0.8, 0.5] } else { [0.8; 4] };
+            (material::STONE, light)
+        });
+        let (naive, greedy) = compare(&quads);
+        assert!(greedy < naive);
+        assert_eq!(greedy, (16 + 1) * 4);
+    }
+
+    #[test]
+    fn speckled() {
+        // Generated blocks differ a little in color and texture turn, which
+        // merging would smear over the whole wall.
+        let mut quads = wall(|_, _| (material::STONE, [0.8; 4]));
+        for q in quads.iter_mut() {
+            if q.pos[0] == 3 { q.color = [0.9, 1.0, 1.0, 1.0]; }
+            if q.pos[0] == 9 && q.pos[2] == 4 { q.textrans = 5; }
+        }
+        let (_, greedy) = compare(&quads);
+        let tinted = super::greedy(quads.iter()).iter()
+            .filter(|v| v.a_color == [0.9, 1.0, 1.0, 1.0]).count();
+        assert_eq!(tinted, 4);
+        // Left of the tinted row, the row itself, and the right side
+        // broken up into five around the turned face.
+        assert_eq!(greedy, (1 + 1 + 5) * 4);
+    }
+}
@@ -5,12 +5,15 @@ use vecmath;
 use camera_controllers::Camera;
 
 use world;
+use fluid::MAX_LEVEL;
 
 const SPEED_HORIZONTAL:        f32 = 2.8;
 const SPEED_HORIZONTAL_CRAWL:  f32 = 1.3;
 const SPEED_VERTICAL:          f32 = 3.0;
 const GRAVITY:                 f32 = 0.2;
 const JUMP_FORCE:              f32 = 10.3;
+const BUOYANCY:                f32 = 0.26;
+const MAX_HEALTH:              f32 = 20.0;
 
 const FRICTION_GROUND:         f32 = 0.5;
 const FRICTION_AIR:            f32 = 0.002;
@@ -49,6 +52,7 @@ pub struct Player {
     pub jump: bool,
     pub on_ground: bool,
     pub noclip: bool,
+    pub health: f32,
     pub debug_info: [[String; 3]; 3],
 }
 
@@ -67,6 +71,7 @@ impl Player {
             jump: false,
             on_ground: true,
             noclip: false,
+            health: MAX_HEALTH,
             debug_info: Default::default(),
         }
     }
@@ -82,6 +87,27 @@ impl Player {
         c
     }
 
+    // Returns true when this was the end of the player.
+    pub fn hurt(&mut self, amount: f32) -> bool {
+        self.health -= amount;
+        self.health <= 0.0
+    }
+
+    pub fn respawn(&mut self, pos: [f64; 3]) {
+        self.pos = pos;
+        self.cam = pos;
+        self.vel = [0.0, 0.0, 0.0];
+        self.health = MAX_HEALTH;
+    }
+
+    // The corners of the body's collision box.
+    pub fn hitbox(&self) -> ([f64; 3], [f64; 3]) {
+        let h = if self.crawl.is_crawling() { HITBOX_HEIGHT_CRAWL } else { HITBOX_HEIGHT };
+        let p = self.pos;
+        ([p[0] - HITBOX_RADIUS, p[1], p[2] - HITBOX_RADIUS],
+         [p[0] + HITBOX_RADIUS, p[1] + h, p[2] + HITBOX_RADIUS])
+    }
+
     pub fn update(&mut self, dt: f32, m: &mut world::Milieu) {
 
         let &mut Player {
@@ -104,7 +130,12 @@ impl Player {
         let (dx, dy, dz) = (dir[0], dir[1], dir[2]);
         let (s, c) = (yaw.sin(), yaw.cos());
 
+        let fluid = if *noclip { None } else {
+            m.fluid_at(pos[0].floor() as i32, (pos[1] + 1.0).floor() as i32, pos[2].floor() as i32)
+        };
+
         let dh =  if crawl.is_crawling() && !*noclip { SPEED_HORIZONTAL_CRAWL } else { SPEED_HORIZONTAL };
+        let dh = match fluid { Some(c) => dh * (1.0 - c.fluid.drag()), None => dh };
         let (mut xo, yo, mut zo) = 
                 ((s * dx - c * dz) * dh,
                 dy * SPEED_VERTICAL,
@@ -125,6 +156,9 @@ impl Player {
 
         let (xo, zo) = (xo * grip, zo * grip);
         let mut accel = [xo, -GRAVITY, zo];
+        if let Some(c) = fluid {
+            accel[1] += BUOYANCY * c.level as f32 / MAX_LEVEL as f32;
+        }
 
         let speed = vecmath::vec3_len(*vel);
         if speed <= STATIC_FRICTION_CUTOFF && *on_ground {
@@ -150,6 +184,10 @@ impl Player {
         vel[0] += accel[0];
         vel[1] += accel[1];
         vel[2] += accel[2];
+        if let Some(c) = fluid {
+            let keep = 1.0 - c.fluid.drag();
+            vel[0] *= keep; vel[1] *= keep; vel[2] *= keep;
+        }
         let mut mov = [
             pos[0] + (vel[0] * dt) as f64,
             pos[1] + (vel[1] * dt) as f64,
@@ -0,0 +1,652 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+
+use world::{Block, Spot};
+use fluid::{Cell, Fluid, MAX_LEVEL};
+use schematic::Clip;
+use material;
+
+// A region holds 8x8x8 chunks. Each region file starts with a table of
+// (offset, length) pairs, one per chunk slot, followed by the chunk blobs.
+const REGION_POT: i32 = 3;
+const REGION_SIZE: i32 = 1 << REGION_POT;
+const REGION_SLOTS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+const HEADER_LEN: usize = 4 + 1 + REGION_SLOTS * 8;
+
+const REGION_MAGIC: &'static [u8; 4] = b"CRVR";
+const LEVEL_MAGIC:  &'static [u8; 4] = b"CRVL";
+const CLIP_MAGIC:   &'static [u8; 4] = b"CRVS";
+const VERSION: u8 = 6;
+
+const LEVEL_FILE: &'static str = "level.dat";
+const GEN_CONFIG_FILE: &'static str = "gen.toml";
+
+const TAG_EMPTY: u8 = 0;
+const TAG_FULL:  u8 = 1;
+const TAG_RICH:  u8 = 2;
+
+// Clips hold at most 128x128x128 cells, each a presence byte and at most a
+// tagged block.
+const CLIP_VOLUME: usize = 1 << 21;
+const MAX_CELL_LEN: usize = 2 + 2 + 4 * 4 + 6;
+
+const TAG_WATER: u8 = 0;
+const TAG_LAVA:  u8 = 1;
+
+pub struct Store {
+    dir: PathBuf,
+    tables: HashMap<(i32, i32, i32), Vec<(u32, u32)>>,
+    touched: HashSet<(i32, i32, i32)>,
+}
+
+impl Store {
+    pub fn open(dir: &Path) -> io::Result<Store> {
+        fs::create_dir_all(dir)?;
+        let mut tables = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if let Some(region) = region_pos(&path) {
+                let mut f = File::open(&path)?;
+                tables.insert(region, read_table(&mut f)?);
+            }
+        }
+        Ok(Store {
+            dir: dir.to_path_buf(),
+            tables,
+            touched: HashSet::new(),
+        })
+    }
+
+    // The seed and the name of the generator the world was created with.
+    pub fn read_level(&self) -> io::Result<Option<(usize, String)>> {
+        let f = match File::open(self.dir.join(LEVEL_FILE)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut level = BufReader::new(f);
+        read_magic(&mut level, LEVEL_MAGIC)?;
+        let seed = read_u64(&mut level)? as usize;
+        let mut name = vec![0; read_u8(&mut level)? as usize];
+        level.read_exact(&mut name)?;
+        match String::from_utf8(name) {
+            Ok(name) => Ok(Some((seed, name))),
+            Err(_) => Err(invalid("generator name is not utf-8")),
+        }
+    }
+
+    pub fn write_level(&self, seed: usize, generator: &str) -> io::Result<()> {
+        if generator.len() > 255 {
+            return Err(invalid("generator name too long"));
+        }
+        let mut level = BufWriter::new(File::create(self.dir.join(LEVEL_FILE))?);
+        level.write_all(LEVEL_MAGIC)?;
+        write_u8(&mut level, VERSION)?;
+        write_u64(&mut level, seed as u64)?;
+        write_u8(&mut level, generator.len() as u8)?;
+        level.write_all(generator.as_bytes())?;
+        level.flush()
+    }
+
+    // The generator config as written, left as text so it reads the same
+    // whatever the config type looks like.
+    pub fn read_gen_config(&self) -> io::Result<Option<String>> {
+        let mut f = match File::open(self.dir.join(GEN_CONFIG_FILE)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut text = String::new();
+        f.read_to_string(&mut text)?;
+        Ok(Some(text))
+    }
+
+    pub fn write_gen_config(&self, text: &str) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(self.dir.join(GEN_CONFIG_FILE))?);
+        f.write_all(text.as_bytes())?;
+        f.flush()
+    }
+
+    // The chunks with changes saved, for telling which need generating
+    // without reading them.
+    pub fn saved(&self) -> Vec<(i32, i32, i32)> {
+        let mut chunks = Vec::new();
+        for (&(rx, ry, rz), table) in self.tables.iter() {
+            for (slot, &(_, len)) in table.iter().enumerate() {
+                if len == 0 { continue; }
+                let slot = slot as i32;
+                chunks.push(((rx << REGION_POT) | (slot >> (2 * REGION_POT)),
+                             (ry << REGION_POT) | ((slot >> REGION_POT) & REGION_SIZE-1),
+                             (rz << REGION_POT) | (slot & REGION_SIZE-1)));
+            }
+        }
+        chunks
+    }
+
+    // One chunk's saved spots and fluid, read on its own when it is needed.
+    pub fn read_chunk(&self, pos: (i32, i32, i32))
+    -> io::Result<Option<(HashMap<u16, Spot>, HashMap<u16, Cell>)>> {
+        let (region, slot) = region_of([pos.0, pos.1, pos.2]);
+        let (offset, len) = match self.tables.get(&region) {
+            Some(table) if table[slot].1 > 0 => table[slot],
+            _ => return Ok(None),
+        };
+        let mut f = File::open(region_path(&self.dir, region))?;
+        if offset as u64 + len as u64 > f.metadata()?.len() {
+            return Err(invalid("region table points past end of file"));
+        }
+        let mut blob = vec![0; len as usize];
+        f.seek(SeekFrom::Start(offset as u64))?;
+        f.read_exact(&mut blob)?;
+        let (at, spots, cells) = read_chunk(&mut Cursor::new(&blob[..]))?;
+        if at != pos {
+            return Err(invalid("chunk saved in another chunk's slot"));
+        }
+        Ok(Some((spots, cells)))
+    }
+
+    // Chunks are appended to the end of their region file and the table entry
+    // is pointed at the new copy; compact() later drops the stale copies.
+    pub fn write_chunk(&mut self, pos: (i32, i32, i32), spots: Option<&HashMap<u16, Spot>>,
+                       cells: &HashMap<u16, Cell>) -> io::Result<()> {
+        let (region, slot) = region_of([pos.0, pos.1, pos.2]);
+        let mut blob = Vec::new();
+        if spots.is_some() || !cells.is_empty() {
+            write_chunk(&mut blob, pos, spots.unwrap_or(&HashMap::new()), cells)?;
+        }
+        if blob.is_empty() && !self.tables.contains_key(&region) {
+            return Ok(());
+        }
+
+        let mut f = OpenOptions::new().read(true).write(true).create(true)
+            .open(region_path(&self.dir, region))?;
+        if f.metadata()?.len() < HEADER_LEN as u64 {
+            f.write_all(REGION_MAGIC)?;
+            write_u8(&mut f, VERSION)?;
+            f.write_all(&vec![0; HEADER_LEN - 5])?;
+        }
+        let offset = f.seek(SeekFrom::End(0))? as u32;
+        f.write_all(&blob)?;
+        f.seek(SeekFrom::Start((5 + slot * 8) as u64))?;
+        write_u32(&mut f, offset)?;
+        write_u32(&mut f, blob.len() as u32)?;
+
+        self.tables.entry(region).or_insert_with(|| vec![(0, 0); REGION_SLOTS])[slot] =
+            (offset, blob.len() as u32);
+        self.touched.insert(region);
+        Ok(())
+    }
+
+    pub fn compact(&mut self) -> io::Result<()> {
+        for region in self.touched.drain() {
+            let path = region_path(&self.dir, region);
+            let mut bytes = Vec::new();
+            File::open(&path)?.read_to_end(&mut bytes)?;
+
+            let table = self.tables.get_mut(&region).unwrap();
+            let mut blobs = Vec::new();
+            for (slot, &(offset, len)) in table.iter().enumerate() {
+                if len == 0 { continue; }
+                let (offset, len) = (offset as usize, len as usize);
+                if offset + len > bytes.len() {
+                    return Err(invalid("region table points past end of file"));
+                }
+                blobs.push((slot, bytes[offset..offset + len].to_vec()));
+            }
+            *table = write_region(&path, blobs)?;
+        }
+        Ok(())
+    }
+}
+
+fn region_of(bigpos: [i32;3]) -> ((i32, i32, i32), usize) {
+    let region = (bigpos[0] >> REGION_POT, bigpos[1] >> REGION_POT, bigpos[2] >> REGION_POT);
+    let (lx, ly, lz) = (bigpos[0] & REGION_SIZE-1,
+                        bigpos[1] & REGION_SIZE-1,
+                        bigpos[2] & REGION_SIZE-1);
+    (region, ((lx * REGION_SIZE + ly) * REGION_SIZE + lz) as usize)
+}
+
+fn region_path(dir: &Path, region: (i32, i32, i32)) -> PathBuf {
+    dir.join(format!("r.{}.{}.{}.rgn", region.0, region.1, region.2))
+}
+
+fn region_pos(path: &Path) -> Option<(i32, i32, i32)> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return None,
+    };
+    let parts: Vec<&str> = name.split('.').collect();
+    if parts.len() != 5 || parts[0] != "r" || parts[4] != "rgn" { return None; }
+    match (parts[1].parse(), parts[2].parse(), parts[3].parse()) {
+        (Ok(x), Ok(y), Ok(z)) => Some((x, y, z)),
+        _ => None,
+    }
+}
+
+fn write_region(path: &Path, blobs: Vec<(usize, Vec<u8>)>) -> io::Result<Vec<(u32, u32)>> {
+    let mut table = vec![(0u32, 0u32); REGION_SLOTS];
+    let mut offset = HEADER_LEN;
+    for &(slot, ref blob) in blobs.iter() {
+        table[slot] = (offset as u32, blob.len() as u32);
+        offset += blob.len();
+    }
+
+    let mut f = BufWriter::new(File::create(path)?);
+    f.write_all(REGION_MAGIC)?;
+    write_u8(&mut f, VERSION)?;
+    for &(offset, len) in table.iter() {
+        write_u32(&mut f, offset)?;
+        write_u32(&mut f, len)?;
+    }
+    for (_, blob) in blobs.into_iter() {
+        f.write_all(&blob)?;
+    }
+    f.flush()?;
+    Ok(table)
+}
+
+fn read_table<R: Read>(r: &mut R) -> io::Result<Vec<(u32, u32)>> {
+    let mut header = vec![0; HEADER_LEN];
+    r.read_exact(&mut header)?;
+    let mut header = Cursor::new(&header[..]);
+    read_magic(&mut header, REGION_MAGIC)?;
+
+    let mut table = Vec::with_capacity(REGION_SLOTS);
+    for _ in 0..REGION_SLOTS {
+        let offset = read_u32(&mut header)?;
+        let len = read_u32(&mut header)?;
+        table.push((offset, len));
+    }
+    Ok(table)
+}
+
+// Chunks only hold the spots that differ from the generated world, followed
+// by the fluid in them.
+fn write_chunk<W: Write>(w: &mut W, pos: (i32, i32, i32), spots: &HashMap<u16, Spot>,
+                         cells: &HashMap<u16, Cell>) -> io::Result<()> {
+    write_i32(w, pos.0)?;
+    write_i32(w, pos.1)?;
+    write_i32(w, pos.2)?;
+    write_u32(w, spots.len() as u32)?;
+    for (&i, s) in spots.iter() {
+        write_u16(w, i)?;
+        write_spot(w, s)?;
+    }
+    write_u32(w, cells.len() as u32)?;
+    for (&i, c) in cells.iter() {
+        write_u16(w, i)?;
+        write_cell(w, c)?;
+    }
+    Ok(())
+}
+
+fn read_chunk<R: Read>(r: &mut R)
+-> io::Result<((i32, i32, i32), HashMap<u16, Spot>, HashMap<u16, Cell>)> {
+    let pos = (read_i32(r)?, read_i32(r)?, read_i32(r)?);
+    let len = read_u32(r)? as usize;
+    if len > 16 * 16 * 16 {
+        return Err(invalid("chunk holds too many spots"));
+    }
+    let mut spots = HashMap::with_capacity(len);
+    for _ in 0..len {
+        spots.insert(read_index(r)?, read_spot(r)?);
+    }
+    let len = read_u32(r)? as usize;
+    if len > 16 * 16 * 16 {
+        return Err(invalid("chunk holds too many fluid cells"));
+    }
+    let mut cells = HashMap::with_capacity(len);
+    for _ in 0..len {
+        cells.insert(read_index(r)?, read_cell(r)?);
+    }
+    Ok((pos, spots, cells))
+}
+
+fn read_index<R: Read>(r: &mut R) -> io::Result<u16> {
+    let i = read_u16(r)?;
+    if i as usize >= 16 * 16 * 16 {
+        return Err(invalid("spot index out of range"));
+    }
+    Ok(i)
+}
+
+fn write_cell<W: Write>(w: &mut W, c: &Cell) -> io::Result<()> {
+    write_u8(w, match c.fluid { Fluid::Water => TAG_WATER, Fluid::Lava => TAG_LAVA })?;
+    write_u8(w, c.level)
+}
+
+fn read_cell<R: Read>(r: &mut R) -> io::Result<Cell> {
+    let fluid = match read_u8(r)? {
+        TAG_WATER => Fluid::Water,
+        TAG_LAVA => Fluid::Lava,
+        _ => return Err(invalid("unknown fluid tag")),
+    };
+    let level = read_u8(r)?;
+    if level == 0 || level > MAX_LEVEL {
+        return Err(invalid("fluid level out of range"));
+    }
+    Ok(Cell { fluid, level })
+}
+
+// Clips share the spot encoding with regions, so they carry the same version.
+// Each cell is a presence byte followed by the spot when present.
+pub fn write_clip<W: Write>(w: &mut W, clip: &Clip) -> io::Result<()> {
+    w.write_all(CLIP_MAGIC)?;
+    write_u8(w, VERSION)?;
+    let size = clip.size();
+    for &n in size.iter() {
+        write_i32(w, n)?;
+    }
+    for x in 0..size[0] { for y in 0..size[1] { for z in 0..size[2] {
+        match clip.get(x, y, z) {
+            Some(s) => { write_u8(w, 1)?; write_spot(w, s)?; },
+            None => write_u8(w, 0)?,
+        }
+    }}}
+    w.flush()
+}
+
+// The payload is read and measured against the size in the header before
+// the clip is allocated, so a bad header cannot ask for more than the file holds.
+pub fn read_clip<R: Read>(r: &mut R) -> io::Result<Clip> {
+    read_magic(r, CLIP_MAGIC)?;
+    let size = [read_i32(r)?, read_i32(r)?, read_i32(r)?];
+    if size.iter().any(|&n| n < 0 || n > 256) {
+        return Err(invalid("clip size out of range"));
+    }
+    let volume = size.iter().map(|&n| n as usize).product::<usize>();
+    if volume > CLIP_VOLUME {
+        return Err(invalid("clip too large"));
+    }
+
+    let mut payload = Vec::new();
+    r.take((volume * MAX_CELL_LEN + 1) as u64).read_to_end(&mut payload)?;
+    if payload.len() < volume {
+        return Err(invalid("clip data shorter than its size"));
+    }
+    let mut cells = Cursor::new(&payload[..]);
+    let mut clip = Clip::new(size);
+    for x in 0..size[0] { for y in 0..size[1] { for z in 0..size[2] {
+        let present = read_u8(&mut cells).map_err(short)?;
+        if present != 0 {
+            clip.set(x, y, z, Some(read_spot(&mut cells).map_err(short)?));
+        }
+    }}}
+    if cells.position() as usize != payload.len() {
+        return Err(invalid("clip data longer than its size"));
+    }
+    Ok(clip)
+}
+
+fn short(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof { invalid("clip data shorter than its size") }
+    else { e }
+}
+
+pub fn write_spot<W: Write>(w: &mut W, s: &Spot) -> io::Result<()> {
+    match *s {
+        Spot::Empty => write_u8(w, TAG_EMPTY),
+        Spot::Full => write_u8(w, TAG_FULL),
+        Spot::Rich(ref b) => {
+            write_u8(w, TAG_RICH)?;
+            write_block(w, b)
+        },
+    }
+}
+
+pub fn read_spot<R: Read>(r: &mut R) -> io::Result<Spot> {
+    match read_u8(r)? {
+        TAG_EMPTY => Ok(Spot::Empty),
+        TAG_FULL => Ok(Spot::Full),
+        TAG_RICH => Ok(Spot::Rich(read_block(r)?)),
+        _ => Err(invalid("unknown spot tag")),
+    }
+}
+
+pub fn write_block<W: Write>(w: &mut W, b: &Block) -> io::Result<()> {
+    write_u16(w, b.material_id())?;
+    for c in b.color().iter() { write_f32(w, *c)?; }
+    w.write_all(&b.textrans())
+}
+
+pub fn read_block<R: Read>(r: &mut R) -> io::Result<Block> {
+    let id = read_u16(r)?;
+    if !material::exists(id) {
+        return Err(invalid("unknown material"));
+    }
+    let mut color = [0.0; 4];
+    for c in color.iter_mut() { *c = read_f32(r)?; }
+    let mut textrans = [0; 6];
+    r.read_exact(&mut textrans)?;
+    if textrans.iter().any(|&t| t >= 8) {
+        return Err(invalid("texture transform out of range"));
+    }
+    Ok(Block::from_parts(id, color, textrans))
+}
+
+fn read_magic<R: Read>(r: &mut R, magic: &[u8; 4]) -> io::Result<()> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    if &buf != magic {
+        return Err(invalid("not a carvel save file"));
+    }
+    if read_u8(r)? != VERSION {
+        return Err(invalid("unsupported save version"));
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u8<W: Write>(w: &mut W, v: u8) -> io::Result<()> {
+    w.write_all(&[v])
+}
+fn write_u16<W: Write>(w: &mut W, v: u16) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+fn write_i32<W: Write>(w: &mut W, v: i32) -> io::Result<()> {
+    write_u32(w, v as u32)
+}
+fn write_f32<W: Write>(w: &mut W, v: f32) -> io::Result<()> {
+    write_u32(w, v.to_bits())
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    Ok(read_u32(r)? as i32)
+}
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(r)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A fresh directory per test, so tests running at once do not share files.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("carvel-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn spots() -> HashMap<u16, Spot> {
+        let mut spots = HashMap::new();
+        spots.insert(0, Spot::Empty);
+        spots.insert(0x123, Spot::Full);
+        spots.insert(0xfff, Spot::Rich(Block::from_parts(material::STONE,
+                                                          [0.5, 0.25, 1.0, 1.0],
+                                                          [0, 1, 2, 3, 4, 7])));
+        spots
+    }
+
+    fn cells() -> HashMap<u16, Cell> {
+        let mut cells = HashMap::new();
+        cells.insert(0x010, Cell { fluid: Fluid::Water, level: MAX_LEVEL });
+        cells.insert(0x011, Cell { fluid: Fluid::Lava, level: 1 });
+        cells
+    }
+
+    fn read_sorted(store: &Store)
+    -> Vec<((i32, i32, i32), HashMap<u16, Spot>, HashMap<u16, Cell>)> {
+        let mut saved = store.saved();
+        saved.sort();
+        saved.into_iter().map(|pos| {
+            let (spots, cells) = store.read_chunk(pos).unwrap().unwrap();
+            (pos, spots, cells)
+        }).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = scratch("round-trip");
+        let mut store = Store::open(&dir).unwrap();
+        store.write_level(77, "flat").unwrap();
+        store.write_chunk((0, 0, 0), Some(&spots()), &HashMap::new()).unwrap();
+        store.write_chunk((-1, 20, 3), Some(&spots()), &cells()).unwrap();
+        store.write_chunk((5, -9, 2), None, &cells()).unwrap();
+        store.compact().unwrap();
+
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.read_level().unwrap(), Some((77, "flat".to_string())));
+        assert_eq!(read_sorted(&store), vec![
+            ((-1, 20, 3), spots(), cells()),
+            ((0, 0, 0), spots(), HashMap::new()),
+            ((5, -9, 2), HashMap::new(), cells()),
+        ]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn table_layout() {
+        let dir = scratch("table-layout");
+        let mut store = Store::open(&dir).unwrap();
+        // Chunk (9, -1, 3) sits at (1, 7, 3) in region (1, -1, 0).
+        store.write_chunk((9, -1, 3), Some(&spots()), &cells()).unwrap();
+        store.compact().unwrap();
+
+        let path = dir.join("r.1.-1.0.rgn");
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], REGION_MAGIC);
+        assert_eq!(bytes[4], VERSION);
+
+        let table = read_table(&mut File::open(&path).unwrap()).unwrap();
+        let slot = (REGION_SIZE + 7) * REGION_SIZE + 3;
+        for (i, &(offset, len)) in table.iter().enumerate() {
+            if i == slot as usize {
+                assert_eq!(offset as usize, HEADER_LEN);
+                assert_eq!(offset as usize + len as usize, bytes.len());
+            } else {
+                assert_eq!((offset, len), (0, 0));
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overwrite_chunk() {
+        let dir = scratch("overwrite");
+        let mut store = Store::open(&dir).unwrap();
+        store.write_chunk((2, 2, 2), Some(&spots()), &cells()).unwrap();
+        store.write_chunk((3, 2, 2), Some(&spots()), &HashMap::new()).unwrap();
+        store.compact().unwrap();
+
+        let mut fewer = HashMap::new();
+        fewer.insert(7, Spot::Full);
+        let mut store = Store::open(&dir).unwrap();
+        store.write_chunk((2, 2, 2), Some(&fewer), &HashMap::new()).unwrap();
+        // Before compacting, the table already points at the new copy.
+        assert_eq!(read_sorted(&store)[0], ((2, 2, 2), fewer.clone(), HashMap::new()));
+        store.write_chunk((3, 2, 2), None, &HashMap::new()).unwrap();
+        store.compact().unwrap();
+
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(read_sorted(&store), vec![((2, 2, 2), fewer, HashMap::new())]);
+        assert!(store.read_chunk((3, 2, 2)).unwrap().is_none());
+        assert!(store.read_chunk((-40, 2, 2)).unwrap().is_none());
+        // Compacting left only the one remaining blob behind the table.
+        let len = fs::metadata(dir.join("r.0.0.0.rgn")).unwrap().len() as usize;
+        let table = read_table(&mut File::open(dir.join("r.0.0.0.rgn")).unwrap()).unwrap();
+        let used: usize = table.iter().map(|&(_, len)| len as usize).sum();
+        assert_eq!(len, HEADER_LEN + used);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn clip_bytes() -> Vec<u8> {
+        let mut clip = Clip::new([3, 2, 4]);
+        clip.set(0, 0, 0, Some(Spot::Empty));
+        clip.set(2, 1, 3, spots().remove(&0xfff));
+        let mut bytes = Vec::new();
+        write_clip(&mut bytes, &clip).unwrap();
+        bytes
+    }
+
+    fn clip_error(bytes: &[u8]) -> String {
+        let e = read_clip(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        e.to_string()
+    }
+
+    #[test]
+    fn clip_round_trip() {
+        let clip = read_clip(&mut Cursor::new(clip_bytes())).unwrap();
+        assert_eq!(clip.size(), [3, 2, 4]);
+        assert_eq!(clip.get(0, 0, 0), Some(&Spot::Empty));
+        assert_eq!(clip.get(2, 1, 3), spots().get(&0xfff));
+        assert_eq!(clip.get(1, 1, 1), None);
+    }
+
+    #[test]
+    fn clip_payload_checked() {
+        let bytes = clip_bytes();
+        let cut = bytes.len() - 3;
+        assert_eq!(clip_error(&bytes[..cut]), "clip data shorter than its size");
+        // Only the presence bytes, with no room for the spots they promise.
+        assert_eq!(clip_error(&bytes[..5 + 12 + 10]), "clip data shorter than its size");
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(clip_error(&longer), "clip data longer than its size");
+
+        // Headers that ask for more than any clip holds fail before reading on.
+        let header = |x: i32, y: i32, z: i32| {
+            let mut b = bytes[..5].to_vec();
+            for &n in [x, y, z].iter() { write_i32(&mut b, n).unwrap(); }
+            b
+        };
+        assert_eq!(clip_error(&header(256, 256, 256)), "clip too large");
+        assert_eq!(clip_error(&header(-1, 2, 2)), "clip size out of range");
+        assert_eq!(clip_error(&header(100, 100, 100)), "clip data shorter than its size");
+    }
+}
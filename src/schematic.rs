@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use world::{Milieu, Spot};
+use shape::Shape;
+use save;
+
+// A box of copied spots. Cells that were outside the copied shape are None
+// and leave the world untouched when pasted; empty cells carve.
+#[derive(Clone, Debug)]
+pub struct Clip {
+    size: [i32;3],
+    cells: Vec<Option<Spot>>,
+}
+
+impl Clip {
+    pub fn new(size: [i32;3]) -> Clip {
+        let volume = (size[0] * size[1] * size[2]).max(0) as usize;
+        Clip {
+            size,
+            cells: vec![None; volume],
+        }
+    }
+
+    pub fn size(&self) -> [i32;3] {
+        self.size
+    }
+
+    fn index(&self, x: i32, y: i32, z: i32) -> Option<usize> {
+        if x < 0 || y < 0 || z < 0
+        || x >= self.size[0] || y >= self.size[1] || z >= self.size[2] { return None; }
+        Some(((x * self.size[1] + y) * self.size[2] + z) as usize)
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<&Spot> {
+        match self.index(x, y, z) {
+            Some(i) => self.cells[i].as_ref(),
+            None => None,
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, s: Option<Spot>) {
+        if let Some(i) = self.index(x, y, z) {
+            self.cells[i] = s;
+        }
+    }
+
+    // Quarter turn around the vertical axis.
+    pub fn rotate(&self) -> Clip {
+        let (sx, sy, sz) = (self.size[0], self.size[1], self.size[2]);
+        let mut clip = Clip::new([sz, sy, sx]);
+        for x in 0..sx { for y in 0..sy { for z in 0..sz {
+            clip.set(sz - 1 - z, y, x, self.get(x, y, z).cloned());
+        }}}
+        clip
+    }
+
+    // Flips the clip along one axis: 0 for x, 1 for y, 2 for z.
+    pub fn mirror(&self, axis: usize) -> Clip {
+        let size = self.size;
+        let mut clip = Clip::new(size);
+        for x in 0..size[0] { for y in 0..size[1] { for z in 0..size[2] {
+            let mut p = [x, y, z];
+            p[axis] = size[axis] - 1 - p[axis];
+            clip.set(p[0], p[1], p[2], self.get(x, y, z).cloned());
+        }}}
+        clip
+    }
+
+    // Pastes with the clip's low corner at origin, as a single undo step.
+    pub fn paste(&self, m: &mut Milieu, origin: [i32;3]) {
+        m.begin_edit();
+        for x in 0..self.size[0] { for y in 0..self.size[1] { for z in 0..self.size[2] {
+            let (wx, wy, wz) = (origin[0] + x, origin[1] + y, origin[2] + z);
+            match self.get(x, y, z) {
+                Some(&Spot::Rich(ref b)) => m.put(wx, wy, wz, b.clone()),
+                Some(&Spot::Empty) => { m.carve(wx, wy, wz); },
+                _ => {},
+            }
+        }}}
+        m.end_edit();
+    }
+
+    // Copies the bounding box of the shape; spots outside the shape stay None.
+    // Natural blocks are copied as the blocks they would reveal.
+    pub fn copy(m: &Milieu, shape: &Shape) -> Clip {
+        let (min, max) = shape.bounds();
+        let mut clip = Clip::new([max[0] - min[0] + 1, max[1] - min[1] + 1, max[2] - min[2] + 1]);
+        for (x, y, z) in shape.positions().into_iter() {
+            clip.set(x - min[0], y - min[1], z - min[2], Some(m.spot_at(x, y, z)));
+        }
+        clip
+    }
+
+    pub fn load(path: &Path) -> io::Result<Clip> {
+        save::read_clip(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save::write_clip(&mut BufWriter::new(File::create(path)?), self)
+    }
+}
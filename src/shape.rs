@@ -0,0 +1,96 @@
+// Regions of blocks for bulk edits. Corners and endpoints are inclusive.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Cuboid { min: [i32;3], max: [i32;3] },
+    Sphere { center: [i32;3], radius: f32 },
+    Cylinder { base: [i32;3], radius: f32, height: i32 },
+    Line { from: [i32;3], to: [i32;3] },
+}
+
+impl Shape {
+    pub fn cuboid(a: [i32;3], b: [i32;3]) -> Shape {
+        Shape::Cuboid {
+            min: [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])],
+            max: [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])],
+        }
+    }
+
+    pub fn bounds(&self) -> ([i32;3], [i32;3]) {
+        match *self {
+            Shape::Cuboid { min, max } => (min, max),
+            Shape::Sphere { center: c, radius } => {
+                let r = radius.floor() as i32;
+                ([c[0] - r, c[1] - r, c[2] - r], [c[0] + r, c[1] + r, c[2] + r])
+            },
+            Shape::Cylinder { base: b, radius, height } => {
+                let r = radius.floor() as i32;
+                let (y0, y1) = if height < 0 { (b[1] + height + 1, b[1]) }
+                               else { (b[1], b[1] + height - 1) };
+                ([b[0] - r, y0, b[2] - r], [b[0] + r, y1, b[2] + r])
+            },
+            Shape::Line { from, to } => {
+                ([from[0].min(to[0]), from[1].min(to[1]), from[2].min(to[2])],
+                 [from[0].max(to[0]), from[1].max(to[1]), from[2].max(to[2])])
+            },
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        let (min, max) = self.bounds();
+        if x < min[0] || y < min[1] || z < min[2]
+        || x > max[0] || y > max[1] || z > max[2] { return false; }
+
+        match *self {
+            Shape::Cuboid { .. } => true,
+            Shape::Sphere { center: c, radius } => {
+                let (dx, dy, dz) = ((x - c[0]) as f32, (y - c[1]) as f32, (z - c[2]) as f32);
+                dx * dx + dy * dy + dz * dz <= radius * radius
+            },
+            Shape::Cylinder { base: b, radius, .. } => {
+                let (dx, dz) = ((x - b[0]) as f32, (z - b[2]) as f32);
+                dx * dx + dz * dz <= radius * radius
+            },
+            Shape::Line { .. } => self.line().contains(&(x, y, z)),
+        }
+    }
+
+    // A spot of the shape with at least one face neighbour outside of it.
+    pub fn is_edge(&self, x: i32, y: i32, z: i32) -> bool {
+        self.contains(x, y, z) && (
+            !self.contains(x + 1, y, z) || !self.contains(x - 1, y, z) ||
+            !self.contains(x, y + 1, z) || !self.contains(x, y - 1, z) ||
+            !self.contains(x, y, z + 1) || !self.contains(x, y, z - 1))
+    }
+
+    pub fn positions(&self) -> Vec<(i32, i32, i32)> {
+        if let Shape::Line { .. } = *self {
+            return self.line();
+        }
+        let (min, max) = self.bounds();
+        let mut positions = Vec::new();
+        for x in min[0]..max[0]+1 { for y in min[1]..max[1]+1 { for z in min[2]..max[2]+1 {
+            if self.contains(x, y, z) {
+                positions.push((x, y, z));
+            }
+        }}}
+        positions
+    }
+
+    fn line(&self) -> Vec<(i32, i32, i32)> {
+        let (from, to) = match *self {
+            Shape::Line { from, to } => (from, to),
+            _ => return Vec::new(),
+        };
+        let d = [to[0] - from[0], to[1] - from[1], to[2] - from[2]];
+        let steps = d[0].abs().max(d[1].abs()).max(d[2].abs());
+        if steps == 0 {
+            return vec![(from[0], from[1], from[2])];
+        }
+        (0..steps+1).map(|i| {
+            let t = i as f32 / steps as f32;
+            ((from[0] as f32 + d[0] as f32 * t).round() as i32,
+             (from[1] as f32 + d[1] as f32 * t).round() as i32,
+             (from[2] as f32 + d[2] as f32 * t).round() as i32)
+        }).collect()
+    }
+}
@@ -0,0 +1,213 @@
+use gen::hash;
+use material::{self, MaterialId};
+
+// How far the longest structure reaches, so a cell always has room for it.
+pub const MAX_EXTENT: i32 = 48;
+
+const MINE_WIDTH: i32 = 5;
+const MINE_HEIGHT: i32 = 5;
+const FRAME_EVERY: i32 = 4;
+const SHAFT_WIDTH: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    // A corridor framed with beams every few blocks, rails down the middle
+    // and a shaft rising from the far end.
+    Mine,
+    // A brick room, its walls broken in places and its floor strewn with rubble.
+    Ruin,
+    // A sealed brick room with gold in the middle.
+    Vault,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Piece {
+    Air,
+    Solid(MaterialId),
+}
+
+// One structure, wholly inside the cell it was planned for. A mine runs
+// along x, or along z when turned.
+#[derive(Clone, Debug)]
+pub struct Structure {
+    pub kind: Kind,
+    pub min: [i32; 3],
+    pub size: [i32; 3],
+    turned: bool,
+    length: i32,
+    rise: i32,
+    seed: usize,
+}
+
+impl Structure {
+    // The structure in a cell of the given size, if it has one. Everything
+    // follows from the seed and the cell, so any spot can ask on its own.
+    pub fn plan(seed: usize, cell: (i32, i32, i32), size: i32, odds: u64) -> Option<Structure> {
+        let (cx, cy, cz) = cell;
+        // Nothing in the cells that reach above ground level.
+        if cy >= 0 { return None; }
+        let h = hash(seed, cx, cy, cz);
+        if h % odds != 0 { return None; }
+        let kind = match (h >> 8) % 3 {
+            0 => Kind::Mine,
+            1 => Kind::Ruin,
+            _ if cy < -1 => Kind::Vault,
+            _ => Kind::Ruin,
+        };
+        let pick = |shift: u32, lo: i32, hi: i32| lo + ((h >> shift) % (hi - lo) as u64) as i32;
+        let turned = (h >> 12) & 1 == 1;
+        let (length, rise, dims) = match kind {
+            Kind::Mine => {
+                let length = pick(16, 16, MAX_EXTENT - SHAFT_WIDTH - 4);
+                let rise = pick(24, 8, 16);
+                (length, rise, [length + SHAFT_WIDTH, rise.max(MINE_HEIGHT), MINE_WIDTH])
+            },
+            Kind::Ruin => (0, 0, [pick(16, 5, 10), pick(24, 4, 6), pick(32, 5, 10)]),
+            Kind::Vault => (0, 0, [7, 5, 7]),
+        };
+        let dims = if turned { [dims[2], dims[1], dims[0]] } else { dims };
+        let place = |c: i32, d: i32, shift: u32|
+            c * size + ((h >> shift) % (size - d + 1) as u64) as i32;
+        Some(Structure {
+            kind,
+            min: [place(cx, dims[0], 40), place(cy, dims[1], 48), place(cz, dims[2], 56)],
+            size: dims,
+            turned,
+            length,
+            rise,
+            seed: h as usize,
+        })
+    }
+
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        let p = [x - self.min[0], y - self.min[1], z - self.min[2]];
+        (0..3).all(|i| p[i] >= 0 && p[i] < self.size[i])
+    }
+
+    // What the structure puts at a spot, or None where it leaves the ground be.
+    pub fn piece(&self, x: i32, y: i32, z: i32) -> Option<Piece> {
+        if !self.contains(x, y, z) { return None; }
+        let (u, w, v) = (x - self.min[0], y - self.min[1], z - self.min[2]);
+        let (u, v) = if self.turned { (v, u) } else { (u, v) };
+        let roll = hash(self.seed, x, y, z);
+        match self.kind {
+            Kind::Mine => self.mine(u, v, w, roll),
+            Kind::Ruin => self.room(u, v, w, roll, true),
+            Kind::Vault => self.room(u, v, w, roll, false),
+        }
+    }
+
+    // u runs along the corridor, v across it and w up. The floor is w = 0,
+    // the corridor is open from w = 1 to 3 and the frames' top beams at w = 4.
+    fn mine(&self, u: i32, v: i32, w: i32, roll: u64) -> Option<Piece> {
+        if u >= self.length {
+            let shaft = v >= 1 && v <= SHAFT_WIDTH && w >= 1 && w < self.rise;
+            return if shaft { Some(Piece::Air) } else { None };
+        }
+        let inside = v >= 1 && v <= 3;
+        // One frame in five has rotted away.
+        let frame = u % FRAME_EVERY == 0 && hash(self.seed, u, 0, 0) % 5 != 0;
+        match w {
+            0 if v == 2 && roll % 7 != 0 => Some(Piece::Solid(material::RAIL)),
+            1..=3 if inside => {
+                if w == 1 && roll % 11 == 0 { Some(Piece::Solid(material::GRAVEL)) }
+                else { Some(Piece::Air) }
+            },
+            1..=3 if frame => Some(Piece::Solid(material::BEAM)),
+            4 if frame => Some(Piece::Solid(material::BEAM)),
+            _ => None,
+        }
+    }
+
+    // A box with brick walls. Ruins have holes where the rock pushed in and
+    // rubble on the floor, vaults are whole and keep their gold.
+    fn room(&self, u: i32, v: i32, w: i32, roll: u64, ruined: bool) -> Option<Piece> {
+        let (lu, lv) = if self.turned { (self.size[2], self.size[0]) }
+                       else { (self.size[0], self.size[2]) };
+        let wall = u == 0 || v == 0 || w == 0
+                || u == lu - 1 || v == lv - 1 || w == self.size[1] - 1;
+        if wall {
+            if ruined && roll % 6 == 0 { return None; }
+            return Some(Piece::Solid(material::BRICK));
+        }
+        if w == 1 {
+            if ruined && roll % 4 == 0 { return Some(Piece::Solid(material::GRAVEL)); }
+            if !ruined && u == lu / 2 && v == lv / 2 { return Some(Piece::Solid(material::GOLD)); }
+        }
+        Some(Piece::Air)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: i32 = 64;
+
+    fn cells() -> Vec<(i32, i32, i32)> {
+        let mut cells = Vec::new();
+        for x in -3..3 { for y in -4..0 { for z in -3..3 {
+            cells.push((x, y, z));
+        }}}
+        cells
+    }
+
+    fn pieces(s: &Structure) -> Vec<Option<Piece>> {
+        let mut pieces = Vec::new();
+        for x in s.min[0]..s.min[0] + s.size[0] {
+            for y in s.min[1]..s.min[1] + s.size[1] {
+                for z in s.min[2]..s.min[2] + s.size[2] {
+                    pieces.push(s.piece(x, y, z));
+                }
+            }
+        }
+        pieces
+    }
+
+    #[test]
+    fn plan_per_cell() {
+        for &c in cells().iter() {
+            let a = Structure::plan(9, c, CELL, 1).unwrap();
+            let b = Structure::plan(9, c, CELL, 1).unwrap();
+            assert_eq!((a.kind, a.min, a.size, a.turned), (b.kind, b.min, b.size, b.turned));
+            assert_eq!(pieces(&a), pieces(&b));
+        }
+        // Asking about another cell first changes nothing.
+        let alone = Structure::plan(9, (1, -2, 1), CELL, 1).unwrap();
+        Structure::plan(9, (0, -2, 1), CELL, 1);
+        let again = Structure::plan(9, (1, -2, 1), CELL, 1).unwrap();
+        assert_eq!(pieces(&alone), pieces(&again));
+    }
+
+    #[test]
+    fn plan_inside_cell() {
+        let mut kinds = Vec::new();
+        for &c in cells().iter() {
+            let s = Structure::plan(4, c, CELL, 1).unwrap();
+            let cell = [c.0, c.1, c.2];
+            for i in 0..3 {
+                assert!(s.size[i] > 0 && s.size[i] <= MAX_EXTENT, "{:?}", s);
+                assert!(s.min[i] >= cell[i] * CELL, "{:?} in {:?}", s, c);
+                assert!(s.min[i] + s.size[i] <= (cell[i] + 1) * CELL, "{:?} in {:?}", s, c);
+            }
+            if !kinds.contains(&s.kind) { kinds.push(s.kind); }
+        }
+        assert_eq!(kinds.len(), 3);
+        for &c in [(0, 0, 0), (2, 5, -1)].iter() {
+            assert!(Structure::plan(4, c, CELL, 1).is_none());
+        }
+    }
+
+    #[test]
+    fn seed_and_odds() {
+        let plans = |seed: usize, odds: u64| -> Vec<Option<[i32; 3]>> {
+            cells().into_iter()
+                .map(|c| Structure::plan(seed, c, CELL, odds).map(|s| s.min))
+                .collect()
+        };
+        assert_eq!(plans(3, 4), plans(3, 4));
+        assert!(plans(3, 4) != plans(5, 4));
+        let some = plans(3, 4).iter().filter(|p| p.is_some()).count();
+        assert!(some > 0 && some < cells().len());
+    }
+}
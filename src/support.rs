@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// No material carries further than this.
+pub const MAX_SPAN: i32 = 12;
+
+// How a solid spot takes part in holding up its layer: how many spots it
+// can carry sideways, and whether it stands on something.
+pub struct Probe {
+    pub span: i32,
+    pub grounded: bool,
+}
+
+const SIDES: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+// Spots in start's layer that are held by nothing: each grounded spot holds
+// the solid spots up to its span away, counted in steps along the layer.
+// Returns the unheld spots connected to start, nearest first.
+pub fn unsupported<F>(start: (i32, i32, i32), probe: F) -> Vec<(i32, i32, i32)>
+        where F: Fn(i32, i32, i32) -> Option<Probe> {
+    let (_, y, _) = start;
+
+    // The solid part of the layer around start. Anything that could hold a
+    // spot within MAX_SPAN of start lies within twice that.
+    let mut region = HashMap::new();
+    let mut queue = VecDeque::new();
+    // A grounded start holds itself, so nothing here is falling.
+    match probe(start.0, y, start.2) {
+        Some(ref p) if p.grounded => return Vec::new(),
+        Some(p) => { region.insert((start.0, start.2), (0, p)); },
+        None => return Vec::new(),
+    }
+    queue.push_back((start.0, start.2));
+    while let Some((x, z)) = queue.pop_front() {
+        let d = region[&(x, z)].0;
+        if d == 2 * MAX_SPAN { continue; }
+        for &(dx, dz) in SIDES.iter() {
+            let q = (x + dx, z + dz);
+            if region.contains_key(&q) { continue; }
+            if let Some(p) = probe(q.0, y, q.1) {
+                region.insert(q, (d + 1, p));
+                queue.push_back(q);
+            }
+        }
+    }
+
+    // What is left of each grounded spot's span when it reaches a spot.
+    let mut held: HashMap<(i32, i32), i32> = HashMap::new();
+    let mut buckets = vec![Vec::new(); MAX_SPAN as usize + 1];
+    for (&q, &(_, ref p)) in region.iter() {
+        if p.grounded {
+            let span = p.span.max(0).min(MAX_SPAN);
+            held.insert(q, span);
+            buckets[span as usize].push(q);
+        }
+    }
+    for v in (1..MAX_SPAN + 1).rev() {
+        let bucket = ::std::mem::replace(&mut buckets[v as usize], Vec::new());
+        for (x, z) in bucket.into_iter() {
+            if held[&(x, z)] != v { continue; }
+            for &(dx, dz) in SIDES.iter() {
+                let q = (x + dx, z + dz);
+                if !region.contains_key(&q) { continue; }
+                if held.get(&q).map_or(true, |&h| h < v - 1) {
+                    held.insert(q, v - 1);
+                    buckets[v as usize - 1].push(q);
+                }
+            }
+        }
+    }
+
+    // The unheld spots reachable from start without crossing a held one.
+    let mut fallen = Vec::new();
+    if held.contains_key(&(start.0, start.2)) { return fallen; }
+    let mut seen = HashSet::new();
+    seen.insert((start.0, start.2));
+    queue.push_back((start.0, start.2));
+    while let Some((x, z)) = queue.pop_front() {
+        fallen.push((x, y, z));
+        for &(dx, dz) in SIDES.iter() {
+            let q = (x + dx, z + dz);
+            if seen.contains(&q) || held.contains_key(&q) { continue; }
+            match region.get(&q) {
+                Some(&(d, _)) if d <= MAX_SPAN => {
+                    seen.insert(q);
+                    queue.push_back(q);
+                },
+                _ => {},
+            }
+        }
+    }
+    fallen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A layer drawn as rows along x: '#' stands on something, '=' only
+    // carries, anything else is open.
+    fn layer(rows: &'static [&'static str], span: i32) -> impl Fn(i32, i32, i32) -> Option<Probe> {
+        move |x, _, z| {
+            if x < 0 || z < 0 { return None; }
+            match rows.get(z as usize).and_then(|r| r.as_bytes().get(x as usize)) {
+                Some(&b'#') => Some(Probe { span, grounded: true }),
+                Some(&b'=') => Some(Probe { span, grounded: false }),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn grounded_column() {
+        assert!(unsupported((0, 4, 0), layer(&["#"], 0)).is_empty());
+        assert!(unsupported((1, 4, 1), layer(&["...", ".#.", "..."], 3)).is_empty());
+        // An open start has nothing to lose.
+        assert!(unsupported((0, 4, 0), layer(&[".#"], 3)).is_empty());
+    }
+
+    #[test]
+    fn overhang_within_span() {
+        assert!(unsupported((5, 4, 0), layer(&["#====="], 5)).is_empty());
+        // Around a corner counts in steps along the layer.
+        assert!(unsupported((2, 4, 3), layer(&["#==", "..=", "..=", "..="], 5)).is_empty());
+        assert_eq!(unsupported((6, 4, 0), layer(&["#======"], 5)), vec![(6, 4, 0)]);
+    }
+
+    #[test]
+    fn bridge_exceeding_span() {
+        let bridge = layer(&["#=========#"], 3);
+        assert_eq!(unsupported((5, 4, 0), &bridge), vec![(5, 4, 0), (6, 4, 0), (4, 4, 0)]);
+        // Starting on a held spot finds nothing, next to the gap finds all of it.
+        assert!(unsupported((3, 4, 0), &bridge).is_empty());
+        assert_eq!(unsupported((4, 4, 0), &bridge).len(), 3);
+        // A shorter bridge is held from both ends.
+        assert!(unsupported((3, 4, 0), layer(&["#=====#"], 3)).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+pub const TICK_LENGTH: f32 = 0.05;
+// Picks per loaded chunk on every tick.
+pub const RANDOM_TICKS: usize = 3;
+// After a long stall only this many ticks are caught up on, the rest are dropped.
+const MAX_CATCH_UP: u32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TickKind {
+    Scheduled,
+    Random,
+}
+
+// Counts fixed length ticks and keeps positions waiting for a given tick.
+// A position is only ever queued once, at its earliest requested tick.
+pub struct Ticker {
+    tick: u64,
+    clock: f32,
+    queue: BinaryHeap<Reverse<(u64, (i32, i32, i32))>>,
+    due: HashMap<(i32, i32, i32), u64>,
+    rng: u64,
+}
+
+impl Ticker {
+    pub fn new(seed: usize) -> Ticker {
+        Ticker {
+            tick: 0,
+            clock: 0.0,
+            queue: BinaryHeap::new(),
+            due: HashMap::new(),
+            rng: seed as u64 ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn schedule(&mut self, pos: (i32, i32, i32), delay: u64) {
+        let at = self.tick + delay.max(1);
+        if let Some(&t) = self.due.get(&pos) {
+            if t <= at { return; }
+        }
+        self.due.insert(pos, at);
+        self.queue.push(Reverse((at, pos)));
+    }
+
+    pub fn is_scheduled(&self, pos: (i32, i32, i32)) -> bool {
+        self.due.contains_key(&pos)
+    }
+
+    // Adds dt to the clock and returns how many ticks have passed.
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        self.clock += dt;
+        let mut n = 0;
+        while self.clock >= TICK_LENGTH {
+            self.clock -= TICK_LENGTH;
+            n += 1;
+        }
+        if n > MAX_CATCH_UP {
+            self.clock = 0.0;
+            n = MAX_CATCH_UP;
+        }
+        n
+    }
+
+    // Moves on by one tick and returns the positions due on it, sorted.
+    pub fn step(&mut self) -> Vec<(i32, i32, i32)> {
+        self.tick += 1;
+        let mut ready = Vec::new();
+        while let Some(&Reverse((at, pos))) = self.queue.peek() {
+            if at > self.tick { break; }
+            self.queue.pop();
+            if self.due.get(&pos) == Some(&at) {
+                self.due.remove(&pos);
+                ready.push(pos);
+            }
+        }
+        ready.sort();
+        ready
+    }
+
+    // A spot index within a chunk, from a xorshift stream so a given seed
+    // always picks the same spots.
+    pub fn random_index(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 32) as usize & 0xFFF
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_in_order() {
+        let mut t = Ticker::new(1);
+        t.schedule((5, 0, 0), 2);
+        t.schedule((1, 0, 0), 2);
+        t.schedule((3, 0, 0), 1);
+        // A delay of zero still waits for the next tick.
+        t.schedule((9, 0, 0), 0);
+        assert_eq!(t.step(), vec![(3, 0, 0), (9, 0, 0)]);
+        assert_eq!(t.step(), vec![(1, 0, 0), (5, 0, 0)]);
+        assert!(t.step().is_empty());
+        assert_eq!(t.now(), 3);
+    }
+
+    #[test]
+    fn earliest_request_wins() {
+        let mut t = Ticker::new(1);
+        t.schedule((0, 0, 0), 5);
+        t.schedule((0, 0, 0), 2);
+        t.schedule((0, 0, 0), 9);
+        assert!(t.is_scheduled((0, 0, 0)));
+        let mut at = Vec::new();
+        for _ in 0..12 {
+            if !t.step().is_empty() { at.push(t.now()); }
+        }
+        assert_eq!(at, vec![2]);
+        assert!(!t.is_scheduled((0, 0, 0)));
+    }
+
+    #[test]
+    fn clock_catches_up() {
+        let mut t = Ticker::new(1);
+        assert_eq!(t.advance(TICK_LENGTH * 0.5), 0);
+        assert_eq!(t.advance(TICK_LENGTH * 0.6), 1);
+        assert_eq!(t.advance(TICK_LENGTH * 3.0), 3);
+        // A long stall is cut short and its remainder dropped.
+        assert_eq!(t.advance(TICK_LENGTH * 100.0), MAX_CATCH_UP);
+        assert_eq!(t.advance(TICK_LENGTH * 0.5), 0);
+    }
+
+    #[test]
+    fn random_picks_follow_seed() {
+        let picks = |seed| {
+            let mut t = Ticker::new(seed);
+            (0..64).map(|_| t.random_index()).collect::<Vec<_>>()
+        };
+        assert_eq!(picks(7), picks(7));
+        assert!(picks(7) != picks(8));
+        assert!(picks(0).iter().all(|&i| i < 16 * 16 * 16));
+    }
+}
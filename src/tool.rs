@@ -1,8 +1,15 @@
-
 use world;
 use player;
+use bag;
+use journal::Change;
+use material::{self, MaterialId};
+use schematic::Clip;
+use shape::Shape;
+
+use std::path::Path;
 
 const INTERACTION_COOLDOWN:    f32 = 0.1;
+const CLIP_FILE: &'static str = "clip.crs";
 
 pub enum InteractionState {
     Idle,
@@ -13,6 +20,13 @@ pub enum InteractionState {
 pub struct Tool {
     pub state: InteractionState,
     pub clock: f32,
+    pub material: MaterialId,
+    // The block being mined and how long it has been worked on.
+    target: Option<(i32, i32, i32)>,
+    progress: f32,
+    mark: Option<(i32, i32, i32)>,
+    pub selection: Option<Shape>,
+    pub clipboard: Option<Clip>,
 }
 
 impl Tool {
@@ -20,10 +34,94 @@ impl Tool {
         Tool {
             state: InteractionState::Idle,
             clock: 0.0,
+            material: material::BRICK,
+            target: None,
+            progress: 0.0,
+            mark: None,
+            selection: None,
+            clipboard: None,
+        }
+    }
+
+    // Letting go of the button loses the progress on the block being mined.
+    pub fn stop(&mut self) {
+        self.state = InteractionState::Idle;
+        self.target = None;
+        self.progress = 0.0;
+    }
+
+    // The first call marks one corner of the selection, the second the other.
+    pub fn select(&mut self, m: &world::Milieu, player: &player::Player) {
+        let c = player.camera();
+        if let (Some((x,y,z)), _) = m.viewcast(c.position, c.forward) {
+            match self.mark.take() {
+                Some((a,b,c)) => self.selection = Some(Shape::cuboid([a,b,c], [x,y,z])),
+                None => self.mark = Some((x,y,z)),
+            }
+        }
+    }
+
+    pub fn copy(&mut self, m: &world::Milieu) {
+        if let Some(ref shape) = self.selection {
+            self.clipboard = Some(Clip::copy(m, shape));
+        }
+    }
+
+    pub fn paste(&mut self, m: &mut world::Milieu, player: &player::Player) {
+        let c = player.camera();
+        if let (_, Some((x,y,z))) = m.viewcast(c.position, c.forward) {
+            if let Some(ref clip) = self.clipboard {
+                clip.paste(m, [x, y, z]);
+            }
+        }
+    }
+
+    pub fn rotate_clip(&mut self) {
+        self.clipboard = self.clipboard.as_ref().map(|c| c.rotate());
+    }
+
+    pub fn mirror_clip(&mut self) {
+        self.clipboard = self.clipboard.as_ref().map(|c| c.mirror(0));
+    }
+
+    pub fn save_clip(&self) {
+        if let Some(ref clip) = self.clipboard {
+            if let Err(e) = clip.save(Path::new(CLIP_FILE)) {
+                println!("Could not save clip: {}", e);
+            }
+        }
+    }
+
+    pub fn load_clip(&mut self) {
+        match Clip::load(Path::new(CLIP_FILE)) {
+            Ok(clip) => self.clipboard = Some(clip),
+            Err(e) => println!("Could not load clip: {}", e),
         }
     }
 
-    pub fn update(&mut self, dt: f32, m: &mut world::Milieu, player: &player::Player) {
+    // Blocks mined in an undone stroke come back out of the bag, and go back
+    // in when it is redone.
+    pub fn undo(&mut self, m: &mut world::Milieu, bag: &mut bag::Bag) {
+        if let Some(group) = m.undo() {
+            for id in drops(&group).into_iter() {
+                bag.take(id);
+            }
+        }
+    }
+
+    pub fn redo(&mut self, m: &mut world::Milieu, bag: &mut bag::Bag) {
+        if let Some(group) = m.redo() {
+            for id in drops(&group).into_iter() {
+                bag.add(id, 1);
+            }
+        }
+    }
+
+    pub fn next_material(&mut self) {
+        self.material = (self.material + 1) % material::all().len() as MaterialId;
+    }
+
+    pub fn update(&mut self, dt: f32, m: &mut world::Milieu, player: &player::Player, bag: &mut bag::Bag) {
         let c = player.camera();
         let (point_full, point_empty) = m.viewcast(c.position, c.forward);
         if let Some((a,b,c)) = point_full{
@@ -31,20 +129,37 @@ impl Tool {
         }
 
         self.clock -= dt;
-        if self.clock > 0.0 { return; }
         
         match self.state {
             InteractionState::Idle => {},
             InteractionState::Mining => {
-                if let Some((x,y,z)) = point_full {
-                    m.pull(x,y,z);
-                    self.clock += INTERACTION_COOLDOWN;
+                // A block comes out once it has been mined for as long as
+                // its hardness takes, looking away starts over.
+                if self.target != point_full {
+                    self.target = point_full;
+                    self.progress = 0.0;
+                }
+                if let Some((x,y,z)) = self.target {
+                    self.progress += dt;
+                    let hardness = match m.world.at(x,y,z) {
+                        Some(&world::Spot::Rich(ref b)) => b.material().hardness,
+                        _ => 0.0,
+                    };
+                    if self.progress < INTERACTION_COOLDOWN * hardness { return; }
+                    if let Some(b) = m.pull(x,y,z) {
+                        if let Some(id) = b.material().drops() {
+                            bag.add(id, 1);
+                        }
+                    }
+                    self.target = None;
+                    self.progress = 0.0;
                 }
             },
             InteractionState::Placing => {
+                if self.clock > 0.0 { return; }
                 if let Some((x,y,z)) = point_empty {
                     m.put(x,y,z, world::Block::new(
-                        0, [1.0, 1.0, 1.0, 1.0]
+                        self.material, 0, material::get(self.material).color
                     ));
                     self.clock += INTERACTION_COOLDOWN;
                 }
@@ -52,4 +167,11 @@ impl Tool {
         }
 
     }
-}
\ No newline at end of file
+}
+
+fn drops(group: &[Change]) -> Vec<MaterialId> {
+    group.iter().filter(|c| c.mined).filter_map(|c| match c.before {
+        world::Spot::Rich(ref b) => b.material().drops(),
+        _ => None,
+    }).collect()
+}
@@ -1,29 +1,48 @@
-use gen::Gen;
+use gen::{self, Gen, WorldGenerator};
+use save::Store;
+use delta::{self, Delta};
+use journal::{Journal, Change};
+use shape::Shape;
+use material::{self, Material, MaterialId, Tiles};
+use jobs::{Pool, Job, Done, Kind};
+use falling::FallingBlock;
+use fluid::{Fluid, Fluids, Cell, MAX_LEVEL};
+use tick::{Ticker, TickKind, RANDOM_TICKS};
+use support::{self, Probe, MAX_SPAN};
+use events::{Observers, WorldEvent};
+use mesh::{self, Quad};
 
 use gfx_voxel::cube;
-use std::collections::HashMap;
-use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::io;
 
 gfx_vertex_struct!( Vertex {
     a_pos: [f32; 3] = "a_pos",
     a_tex_coord: [f32; 2] = "a_tex_coord",
     a_color: [f32; 4] = "a_color",
     a_light: f32 = "a_light",
+    a_tile: [f32; 4] = "a_tile",
 });
 
 impl Vertex {
-    fn new(pos: [f32; 3], tc: [f32; 2], col: [f32; 4], light: f32) -> Vertex {
+    // Texture coordinates count blocks, the shader wraps them into the
+    // part of the tile one block shows, so merged faces repeat the tile
+    // once per block.
+    pub fn new(pos: [f32; 3], tc: [f32; 2], tile: [f32; 4], col: [f32; 4], light: f32) -> Vertex {
         Vertex {
             a_pos: pos,
             a_tex_coord: [tc[0], tc[1]],
             a_color: col,
             a_light: light,
+            a_tile: [tile[0], tile[1], tile[2] / TEXWIDTH, tile[3] / TEXWIDTH],
         }
     }
 }
 
 const TEXWIDTH:f32 = 4.0;
-const TRANS:[[[i32;4];2];8] = [
+pub const TRANS:[[[i32;4];2];8] = [
     [[1,0,0,1,],[1,1,0,0,]],
     [[0,0,1,1,],[1,0,0,1,]],
     [[0,1,1,0,],[0,0,1,1,]],
@@ -34,27 +53,44 @@ const TRANS:[[[i32;4];2];8] = [
     [[0,0,1,1,],[0,1,1,0,]],
 ];
 
-#[derive(Clone,Debug)]
+#[derive(Clone,Debug,PartialEq)]
 pub struct Block {
+    material: MaterialId,
     color: [f32;4],
-    textrans: [[[i32;4];2];6],
-    vertices: RefCell<Vec<Vertex>>,
+    textrans: [u8;6],
 }
 
 impl Block {
-    pub fn new(rng: usize, c: [f32;4]) -> Block {
+    pub fn new(material: MaterialId, rng: usize, c: [f32;4]) -> Block {
         Block {
+            material,
             color: c,
-            textrans: [TRANS[(rng>>00)%8], TRANS[(rng>>03)%8], TRANS[(rng>>06)%8], 
-                       TRANS[(rng>>09)%8], TRANS[(rng>>12)%8], TRANS[(rng>>15)%8], ],
-            vertices: RefCell::new(Vec::new()),
+            textrans: [((rng>>00)%8) as u8, ((rng>>03)%8) as u8, ((rng>>06)%8) as u8,
+                       ((rng>>09)%8) as u8, ((rng>>12)%8) as u8, ((rng>>15)%8) as u8, ],
         }
     }
-    pub fn get_vertex_data(&self) -> &RefCell<Vec<Vertex>> {
-        &self.vertices
+    pub fn from_parts(material: MaterialId, color: [f32;4], textrans: [u8;6]) -> Block {
+        Block {
+            material,
+            color,
+            textrans,
+        }
     }
-    pub fn update_surface(&self, x: i32, y: i32, z: i32, w: &InfiniteWorld, shiny: f32) {
-        let mut vertices = Vec::new();
+    pub fn material_id(&self) -> MaterialId {
+        self.material
+    }
+    pub fn material(&self) -> &'static Material {
+        material::get(self.material)
+    }
+    pub fn color(&self) -> [f32;4] {
+        self.color
+    }
+    pub fn textrans(&self) -> [u8;6] {
+        self.textrans
+    }
+    pub fn surface(&self, x: i32, y: i32, z: i32, w: &InfiniteWorld, tile: [f32;4], shiny: f32)
+    -> Vec<Quad> {
+        let mut quads = Vec::new();
 
         for f in 0..6 {
             let face = cube::Face::from_usize(f).unwrap();
@@ -62,25 +98,47 @@ impl Block {
             if let Some(&Empty) = w.at((d[0] + x), (d[1] + y), (d[2] + z)){
 
                 let v = vertices_int(f, [x, y, z]);
+                let mut light = [0.0; 4];
                 for i in 0..4{
-                    vertices.push(Vertex::new(
-                        [v[i][0] as f32, v[i][1] as f32, v[i][2] as f32],
-                            [self.textrans[f][0][i] as f32 / TEXWIDTH,
-                             self.textrans[f][1][i] as f32 / TEXWIDTH],
-                        self.color,
-                        shiny * get_light(f, get_surroundings(v[i], w))
-                    ));
+                    light[i] = shiny * get_light(f, get_occlusion(f, [x, y, z], v[i], w));
                 }
+                quads.push(Quad {
+                    face: f,
+                    pos: [x, y, z],
+                    material: self.material,
+                    tile,
+                    color: self.color,
+                    textrans: self.textrans[f],
+                    light,
+                });
+            }
+        }
+        quads
+    }
+    // All six faces at a free position, for blocks that are not in the grid.
+    pub fn cube(&self, pos: [f32;3], tile: [f32;4]) -> Vec<Vertex> {
+        let mut vertices = Vec::new();
+        for f in 0..6 {
+            let v = vertices_int(f, [0, 0, 0]);
+            let t = TRANS[self.textrans[f] as usize];
+            for i in 0..4 {
+                vertices.push(Vertex::new(
+                    [pos[0] + v[i][0] as f32, pos[1] + v[i][1] as f32, pos[2] + v[i][2] as f32],
+                    [t[0][i] as f32, t[1][i] as f32],
+                    tile,
+                    self.color,
+                    get_light(f, 3)
+                ));
             }
         }
-        *self.vertices.borrow_mut() = vertices;
+        vertices
     }
 }
 
-fn get_light(face: usize, crowd: u8) -> f32{
-    let a = match crowd {
-        0 => 0.0,
-        1 => 0.5,
+fn get_light(face: usize, open: u8) -> f32{
+    let a = match open {
+        0 => 0.5,
+        1 => 0.65,
         2 => 0.8,
         _ => 1.0,
     };
@@ -95,22 +153,32 @@ fn get_light(face: usize, crowd: u8) -> f32{
     }
 }
 
-fn get_surroundings(pos: [i32;3], w: &InfiniteWorld) -> u8{
-    let (x,y,z) = (pos[0], pos[1], pos[2]);
-    let mut total = 0;
-    if let Some(&Empty) = w.at(x,y,z) { total += 1; }
-    if let Some(&Empty) = w.at(x,y,z-1) { total += 1; }
-    if let Some(&Empty) = w.at(x,y-1,z) { total += 1; }
-    if let Some(&Empty) = w.at(x,y-1,z-1) { total += 1; }
-    if let Some(&Empty) = w.at(x-1,y,z) { total += 1; }
-    if let Some(&Empty) = w.at(x-1,y,z-1) { total += 1; }
-    if let Some(&Empty) = w.at(x-1,y-1,z) { total += 1; }
-    if let Some(&Empty) = w.at(x-1,y-1,z-1) { total += 1; }
-    total
+// How open a corner of a face is, from 0 to 3, judged by the two blocks
+// beside the corner and the one diagonal to it in the layer the face looks
+// into. Both sides blocked shuts the corner whatever the diagonal is.
+fn get_occlusion(face: usize, pos: [i32;3], corner: [i32;3], w: &InfiniteWorld) -> u8{
+    let d = cube::Face::from_usize(face).unwrap().direction();
+    let front = [pos[0] + d[0], pos[1] + d[1], pos[2] + d[2]];
+    let mut sides = [[0;3];2];
+    let mut k = 0;
+    for i in 0..3 {
+        if d[i] == 0 {
+            sides[k][i] = if corner[i] > pos[i] { 1 } else { -1 };
+            k += 1;
+        }
+    }
+    let solid = |s: [i32;3]| match w.at(front[0] + s[0], front[1] + s[1], front[2] + s[2]) {
+        Some(&Empty) => false,
+        _ => true,
+    };
+    let (a, b) = (sides[0], sides[1]);
+    let (sa, sb) = (solid(a), solid(b));
+    if sa && sb { return 0; }
+    3 - sa as u8 - sb as u8 - solid([a[0] + b[0], a[1] + b[1], a[2] + b[2]]) as u8
 }
 
 // Stolen/modified from gfx_voxel to use ints rather than floats
-const CUBE_VERTICES: &'static [[i32;3]; 8] = &[
+pub const CUBE_VERTICES: &'static [[i32;3]; 8] = &[
     [0, 0, 0], // 0
     [1, 0, 0], // 1
     [1, 1, 0], // 2
@@ -121,6 +189,23 @@ const CUBE_VERTICES: &'static [[i32;3]; 8] = &[
     [1, 1, 1]  // 7
 ];
 
+// One face of the box from min to max, for shapes that are not whole blocks.
+pub fn box_face(f: usize, min: [f32;3], max: [f32;3], tile: [f32;4], color: [f32;4])
+-> Vec<Vertex> {
+    let t = TRANS[0];
+    cube::QUADS[f].iter().enumerate().map(|(i, &c)| {
+        let v = CUBE_VERTICES[c];
+        let pick = |k: usize| if v[k] == 0 { min[k] } else { max[k] };
+        Vertex::new(
+            [pick(0), pick(1), pick(2)],
+            [t[0][i] as f32, t[1][i] as f32],
+            tile,
+            color,
+            get_light(f, 3)
+        )
+    }).collect()
+}
+
 // Stolen/modified from gfx_voxel to use ints rather than floats
 fn vertices_int(face: usize, base: [i32;3]) -> [[i32;3]; 4] {
     use gfx_voxel::array::*;
@@ -134,13 +219,13 @@ fn vertices_int(face: usize, base: [i32;3]) -> [[i32;3]; 4] {
     })
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Spot {
     Empty,
     Full,
-    Rich(Box<Block>),
+    Rich(Block),
 }
-use Spot::{Empty, Full, Rich};
+use self::Spot::{Empty, Full, Rich};
 impl Default for Spot {
     fn default() -> Spot { Full }
 }
@@ -155,97 +240,181 @@ impl Spot {
         else if let &Full = self { false }
         else { true }
     }
-    pub fn unwrap_mut(&mut self) -> &mut Box<Block> {
-        if let &mut Rich(ref mut b) = self { b }
-        else {panic!("Unwrapped a Spot with a non-rich value");}
-    }
 }
 
 const SIZE_I: i32 = 16;
 const SIZE_U: usize = SIZE_I as usize;
 const POT: i32 = 4;
+const VOLUME: usize = SIZE_U * SIZE_U * SIZE_U;
 
-#[derive(Debug)]
+// Spots are stored as indices into a per-chunk palette of distinct states,
+// packed into u64 words at 0, 1, 2, 4, 8 or 16 bits per spot.
+#[derive(Clone, Debug)]
 pub struct Chunk {
     pub bigpos: [i32;3],
-    small: [[[Spot;SIZE_U];SIZE_U];SIZE_U],
-    filled: bool,
-    pub request: RefCell<bool>,
+    palette: Vec<Spot>,
+    refs: Vec<u16>,
+    bits: usize,
+    packed: Vec<u64>,
+    meshes: HashMap<u16, Vec<Quad>>,
+    pub version: u64,
+}
+
+fn index(x: usize, y: usize, z: usize) -> usize {
+    assert!(x < SIZE_U && y < SIZE_U && z < SIZE_U);
+    (x << (2 * POT)) | (y << POT) | z
 }
 
 impl Chunk {
-    fn new_full(x: i32, y: i32, z: i32) -> Chunk {
+    pub fn new_full(x: i32, y: i32, z: i32) -> Chunk {
         Chunk{
             bigpos: [x, y, z],
-            small: Default::default(),
-            filled: true,
-            request: RefCell::new(true),
+            palette: vec![Full],
+            refs: vec![VOLUME as u16],
+            bits: 0,
+            packed: Vec::new(),
+            meshes: HashMap::new(),
+            version: 0,
         }
     }
-    /*pub fn new_empty(x: i32, y: i32, z: i32) -> Chunk {
-        Chunk{
-            bigpos: [x, y, z],
-            small: Default::default(),
-            filled: false,
-            request: false,
-            vertex_data: Vec::new(),
-        }
-    }*/
-    fn at(&self, x: usize, y: usize, z: usize) -> &Spot {
-        assert!(x < SIZE_U && y < SIZE_U && z < SIZE_U);
-        &self.small[x][y][z]
-    }
-    fn at_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Spot {
-        assert!(x < SIZE_U && y < SIZE_U && z < SIZE_U);
-        &mut self.small[x][y][z]
-    }
-    fn put(&mut self, x: usize, y: usize, z: usize, b: Block) -> &mut Block {
-        assert!(x < SIZE_U && y < SIZE_U && z < SIZE_U);
-        self.small[x][y][z] = Rich(Box::new(b));
-        self.at_mut(x, y, z).unwrap_mut()
-    }
-    fn yank(&mut self, x: usize, y: usize, z: usize) -> Option<Block> {
-        assert!(x < SIZE_U && y < SIZE_U && z < SIZE_U);
-        if let Rich(b) = self.small[x][y][z].clone(){
-            self.small[x][y][z] = Empty;
-            Some(*b)
-        } else {
-            None
+    // Rebuilds a chunk from the generator and the recorded changes, revealing
+    // every solid spot that borders a dug out one.
+    pub fn generate(bx: i32, by: i32, bz: i32, gen: &dyn WorldGenerator, delta: &Delta) -> Chunk {
+        let mut c = Chunk::new_full(bx, by, bz);
+        if let Some(spots) = delta.chunk(bx, by, bz) {
+            for (&i, s) in spots.iter() {
+                let i = i as usize;
+                c.set((i >> 8) & 15, (i >> 4) & 15, i & 15, s.clone());
+            }
         }
-    }
-    fn update(&self) {
-        *self.request.borrow_mut() = true;
-    }
-    fn build_surface(&self) -> Vec<Vertex> {
-        let mut vertices = Vec::<Vertex>::new();
 
-        for x in 0..SIZE_I { for y in 0..SIZE_I { for z in 0..SIZE_I {
-            if let &Rich(ref b) = self.at(x as usize, y as usize, z as usize) {
-                vertices.extend_from_slice(
-                    b.get_vertex_data()
-                    .borrow().as_slice());
+        let (ox, oy, oz) = (bx << POT, by << POT, bz << POT);
+        for x in 0..SIZE_U { for y in 0..SIZE_U { for z in 0..SIZE_U {
+            if let &Full = c.at(x, y, z) {
+                let (gx, gy, gz) = (ox + x as i32, oy + y as i32, oz + z as i32);
+                let open = cube::FaceIterator::new().any(|f| {
+                    let d = f.direction();
+                    match delta.get(gx + d[0], gy + d[1], gz + d[2]) {
+                        Some(&Empty) => true,
+                        _ => false,
+                    }
+                });
+                // Cave air next to a dug out spot is left hidden, the
+                // milieu opens it up once the chunk is loaded.
+                if open {
+                    if let Rich(b) = gen.spot(gx, gy, gz) {
+                        c.set(x, y, z, Rich(b));
+                    }
+                }
             }
         }}}
-        vertices
+        c
+    }
+    // True while every spot is still untouched solid ground.
+    pub fn is_solid(&self) -> bool {
+        self.palette.iter().zip(self.refs.iter()).all(|(s, &r)| r == 0 || *s == Full)
+    }
+    fn get_index(&self, i: usize) -> usize {
+        if self.bits == 0 { return 0; }
+        let per = 64 / self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.packed[i / per] >> ((i % per) * self.bits)) & mask) as usize
+    }
+    fn set_index(&mut self, i: usize, p: usize) {
+        let per = 64 / self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        let shift = (i % per) * self.bits;
+        let word = &mut self.packed[i / per];
+        *word = (*word & !(mask << shift)) | ((p as u64) << shift);
+    }
+    fn grow(&mut self) {
+        let indices: Vec<usize> = (0..VOLUME).map(|i| self.get_index(i)).collect();
+        self.bits = if self.bits == 0 { 1 } else { self.bits * 2 };
+        self.packed = vec![0; VOLUME / (64 / self.bits)];
+        for (i, p) in indices.into_iter().enumerate() {
+            self.set_index(i, p);
+        }
+    }
+    fn intern(&mut self, s: Spot) -> usize {
+        if let Some(p) = (0..self.palette.len()).find(|&p| self.refs[p] > 0 && self.palette[p] == s) {
+            self.refs[p] += 1;
+            return p;
+        }
+        let p = match self.refs.iter().position(|&r| r == 0) {
+            Some(p) => { self.palette[p] = s; p },
+            None => {
+                self.palette.push(s);
+                self.refs.push(0);
+                self.palette.len() - 1
+            },
+        };
+        self.refs[p] = 1;
+        while self.palette.len() > 1 << self.bits {
+            self.grow();
+        }
+        p
+    }
+    pub fn at(&self, x: usize, y: usize, z: usize) -> &Spot {
+        &self.palette[self.get_index(index(x, y, z))]
+    }
+    pub fn set(&mut self, x: usize, y: usize, z: usize, s: Spot) {
+        let i = index(x, y, z);
+        let old = self.get_index(i);
+        self.meshes.remove(&(i as u16));
+        if self.palette[old] == s { return; }
+
+        self.refs[old] -= 1;
+        if self.refs[old] == 0 { self.palette[old] = Full; }
+        let p = self.intern(s);
+        if self.bits != 0 { self.set_index(i, p); }
+    }
+    fn set_mesh(&mut self, x: usize, y: usize, z: usize, quads: Vec<Quad>) {
+        let i = index(x, y, z) as u16;
+        if quads.is_empty() { self.meshes.remove(&i); }
+        else { self.meshes.insert(i, quads); }
+    }
+    fn set_meshes(&mut self, meshes: HashMap<u16, Vec<Quad>>) {
+        self.meshes = meshes;
+    }
+    pub fn build_surface(&self) -> Vec<Vertex> {
+        mesh::greedy(self.meshes.values().flat_map(|q| q.iter()))
     }
 }
 
+// The voxel a ray stopped at. The normal points out of the face the ray
+// entered through and is zero when the ray started inside the voxel.
+pub struct RayHit<'a> {
+    pub pos: (i32, i32, i32),
+    pub normal: [i32;3],
+    pub point: [f32;3],
+    pub distance: f32,
+    pub spot: &'a Spot,
+}
+
+// Chunks are shared with worker threads through Arc and copied on write, so
+// a job can keep reading its snapshot while the game goes on editing.
 pub struct InfiniteWorld {
-    chunks: HashMap<(i32, i32, i32), Chunk>,
+    chunks: HashMap<(i32, i32, i32), Arc<Chunk>>,
+    dirty: HashSet<(i32, i32, i32)>,
+    stamp: u64,
 }
-use std::collections::hash_map::ValuesMut;
+use std::collections::hash_map::Values;
 
 impl InfiniteWorld {
     pub fn new_full() -> InfiniteWorld{
         InfiniteWorld{
             chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            stamp: 0,
         }
     }
     fn get_chunk(&self, x: i32, y: i32, z: i32) -> Option<&Chunk>{
-        self.chunks.get(&(x, y, z))
+        self.chunks.get(&(x, y, z)).map(|c| &**c)
     }
     pub fn get_chunk_mut(&mut self, x: i32, y: i32, z: i32) -> &mut Chunk{
-        self.chunks.entry((x, y, z)).or_insert(Chunk::new_full(x, y, z))
+        self.dirty.insert((x, y, z));
+        let c = self.chunks.entry((x, y, z)).or_insert_with(|| Arc::new(Chunk::new_full(x, y, z)));
+        Arc::make_mut(c)
     }
     fn splice(&self, x: i32, y: i32, z: i32)
     -> Option<(&Chunk, usize, usize, usize)>{
@@ -265,138 +434,1114 @@ impl InfiniteWorld {
                             (y & SIZE_I-1) as usize,
                             (z & SIZE_I-1) as usize);
         let c = self.get_chunk_mut(bx, by, bz);
-        c.update();
         (c, sx, sy, sz)
     }
+    // Changes a spot and bumps the chunk's version, which tells pending
+    // mesh jobs that read the old contents that they are out of date.
+    pub fn set(&mut self, x: i32, y: i32, z: i32, s: Spot) {
+        self.stamp += 1;
+        let stamp = self.stamp;
+        let (c, sx, sy, sz) = self.splice_mut(x, y, z);
+        c.set(sx, sy, sz, s);
+        c.version = stamp;
+    }
     pub fn at(&self, x: i32, y: i32, z: i32) -> Option<&Spot>{
         match self.splice(x, y, z){
             Some((c, sx, sy, sz)) => Some(&c.at(sx, sy, sz)),
             None => None
         }
     }
-    pub fn at_update(&self, x: i32, y: i32, z: i32) -> Option<&Spot>{
-        match self.splice(x, y, z){
-            Some((c, sx, sy, sz)) => {c.update(); Some(&c.at(sx, sy, sz))},
-            None => None
+    // Walks the voxels along the ray in order and returns the first one whose
+    // spot passes the filter. Spots in chunks that are not loaded are skipped.
+    pub fn raycast<F>(&self, origin: [f32;3], dir: [f32;3], max: f32, filter: F)
+            -> Option<RayHit> where F: Fn(&Spot) -> bool {
+        let len = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+        // NaN or infinite parts would keep the walk from ever moving on.
+        if !(len > 0.0 && len.is_finite() && max >= 0.0) { return None; }
+        let d = [dir[0] / len, dir[1] / len, dir[2] / len];
+
+        let mut v = [origin[0].floor() as i32, origin[1].floor() as i32, origin[2].floor() as i32];
+        let mut step = [0; 3];
+        let mut next = [::std::f32::INFINITY; 3];
+        let mut delta = [::std::f32::INFINITY; 3];
+        for i in 0..3 {
+            if d[i] > 0.0 {
+                step[i] = 1;
+                delta[i] = 1.0 / d[i];
+                next[i] = (v[i] as f32 + 1.0 - origin[i]) * delta[i];
+            } else if d[i] < 0.0 {
+                step[i] = -1;
+                delta[i] = -1.0 / d[i];
+                next[i] = (origin[i] - v[i] as f32) * delta[i];
+            }
+        }
+
+        let mut normal = [0; 3];
+        let mut t = 0.0;
+        loop {
+            if let Some(s) = self.at(v[0], v[1], v[2]) {
+                if filter(s) {
+                    return Some(RayHit {
+                        pos: (v[0], v[1], v[2]),
+                        normal,
+                        point: [origin[0] + d[0] * t, origin[1] + d[1] * t, origin[2] + d[2] * t],
+                        distance: t,
+                        spot: s,
+                    });
+                }
+            }
+            let i = if next[0] < next[1] {
+                if next[0] < next[2] { 0 } else { 2 }
+            } else {
+                if next[1] < next[2] { 1 } else { 2 }
+            };
+            t = next[i];
+            if t > max { return None; }
+            v[i] += step[i];
+            next[i] += delta[i];
+            normal = [0; 3];
+            normal[i] = -step[i];
         }
     }
-    pub fn chunks(&mut self) -> ValuesMut<(i32, i32, i32), Chunk>{
-        self.chunks.values_mut()
+    pub fn iter(&self) -> Values<(i32, i32, i32), Arc<Chunk>>{
+        self.chunks.values()
+    }
+    pub fn insert_chunk(&mut self, mut c: Chunk){
+        let (x, y, z) = (c.bigpos[0], c.bigpos[1], c.bigpos[2]);
+        self.stamp += 1;
+        c.version = self.stamp;
+        self.chunks.insert((x, y, z), Arc::new(c));
+        self.dirty.insert((x, y, z));
+    }
+    pub fn remove_chunk(&mut self, x: i32, y: i32, z: i32) -> Option<Arc<Chunk>>{
+        self.dirty.remove(&(x, y, z));
+        self.chunks.remove(&(x, y, z))
+    }
+    pub fn has_chunk(&self, x: i32, y: i32, z: i32) -> bool{
+        self.chunks.contains_key(&(x, y, z))
+    }
+    pub fn take_dirty(&mut self) -> Vec<(i32, i32, i32)> {
+        self.dirty.drain().collect()
+    }
+    // The chunk and its 26 neighbours, sharing the chunks rather than copying them.
+    pub fn snapshot(&self, bx: i32, by: i32, bz: i32) -> InfiniteWorld {
+        let mut w = InfiniteWorld::new_full();
+        for x in bx-1..bx+2 { for y in by-1..by+2 { for z in bz-1..bz+2 {
+            if let Some(c) = self.chunks.get(&(x, y, z)) {
+                w.chunks.insert((x, y, z), c.clone());
+            }
+        }}}
+        w
+    }
+    // Versions of the chunks a mesh of (bx, by, bz) depends on, None where missing.
+    pub fn versions_around(&self, bx: i32, by: i32, bz: i32) -> Vec<Option<u64>> {
+        let mut v = Vec::with_capacity(27);
+        for x in bx-1..bx+2 { for y in by-1..by+2 { for z in bz-1..bz+2 {
+            v.push(self.chunks.get(&(x, y, z)).map(|c| c.version));
+        }}}
+        v
+    }
+    pub fn mesh_chunk(&self, bx: i32, by: i32, bz: i32, tiles: &Tiles) -> HashMap<u16, Vec<Quad>> {
+        let mut meshes = HashMap::new();
+        let c = match self.get_chunk(bx, by, bz) {
+            Some(c) => c,
+            None => return meshes,
+        };
+        let (ox, oy, oz) = (bx << POT, by << POT, bz << POT);
+        for x in 0..SIZE_U { for y in 0..SIZE_U { for z in 0..SIZE_U {
+            if let &Rich(ref b) = c.at(x, y, z) {
+                let (gx, gy, gz) = (ox + x as i32, oy + y as i32, oz + z as i32);
+                let quads = b.surface(gx, gy, gz, self, tiles.get(b.material_id()), 1.0);
+                if !quads.is_empty() {
+                    meshes.insert(index(x, y, z) as u16, quads);
+                }
+            }
+        }}}
+        meshes
+    }
+    fn set_meshes(&mut self, bx: i32, by: i32, bz: i32, meshes: HashMap<u16, Vec<Quad>>) {
+        if self.has_chunk(bx, by, bz) {
+            self.get_chunk_mut(bx, by, bz).set_meshes(meshes);
+        }
     }
 }
 
+const KEEP_DISTANCE: i32 = 8;
+const FLUID_EVERY: u64 = 4;
+const COLLAPSE_LIMIT: usize = 256;
+const DEBRIS_DAMAGE: f32 = 0.4;
+const VIEW_REACH: f32 = 10.0;
+// Cave spots opened when a cave is broken into, and again on each tick
+// after that until the whole cave is open.
+const CAVE_STEP: usize = 512;
+
 pub struct Milieu {
     pub world: InfiniteWorld,
     surfacecache: HashMap<(i32, i32, i32), Vec<Vertex>>,
-    gen: Gen,
+    gen: Arc<dyn WorldGenerator>,
+    generator: String,
+    seed: usize,
+    tiles: Arc<Tiles>,
+    delta: Delta,
+    journal: Journal,
+    store: Option<Store>,
+    // Chunks whose saved changes have been read into the delta. The rest are
+    // read from the store when something first needs them.
+    fetched: HashSet<(i32, i32, i32)>,
+    keep_distance: i32,
+    center: Option<(i32, i32, i32)>,
     shiny: Vec<(i32, i32, i32, f32)>,
+    falling: Vec<FallingBlock>,
+    fluids: Fluids,
+    ticker: Ticker,
+    observers: Observers,
+    fluidcache: Vec<Vertex>,
+    caves: VecDeque<(i32, i32, i32)>,
+    // Cave spots reached out of range, by chunk, opened once it loads.
+    far_caves: HashMap<(i32, i32, i32), HashSet<(i32, i32, i32)>>,
+    pool: Pool,
+    // Chunks being generated, and whether an edit is waiting for them.
+    generating: HashMap<(i32, i32, i32), bool>,
+    // Chunks being meshed or surfaced, and whether they were asked for again
+    // meanwhile.
+    meshing: HashMap<(i32, i32, i32), bool>,
+    surfacing: HashMap<(i32, i32, i32), bool>,
 }
 
 impl Milieu {
     pub fn new_full(seed: usize) -> Milieu{
+        Milieu::with_generator(seed, gen::DEFAULT_GENERATOR, Arc::new(Gen::new(seed)))
+    }
+    // The name is saved with the world, so a built in generator is found
+    // again when the world is loaded.
+    pub fn with_generator(seed: usize, name: &str, gen: Arc<dyn WorldGenerator>) -> Milieu{
         Milieu{
             world: InfiniteWorld::new_full(),
             surfacecache: HashMap::new(),
-            gen: Gen::new(seed),
+            gen,
+            generator: name.to_string(),
+            seed,
+            tiles: Arc::new(Tiles::new()),
+            delta: Delta::new(),
+            journal: Journal::new(),
+            store: None,
+            fetched: HashSet::new(),
+            keep_distance: KEEP_DISTANCE,
+            center: None,
             shiny: Vec::new(),
+            falling: Vec::new(),
+            fluids: Fluids::new(),
+            ticker: Ticker::new(seed),
+            observers: Observers::new(),
+            fluidcache: Vec::new(),
+            caves: VecDeque::new(),
+            far_caves: HashMap::new(),
+            pool: Pool::new(),
+            generating: HashMap::new(),
+            meshing: HashMap::new(),
+            surfacing: HashMap::new(),
+        }
+    }
+    pub fn seed(&self) -> usize {
+        self.seed
+    }
+    pub fn generator(&self) -> &str {
+        &self.generator
+    }
+    pub fn delta(&self) -> &Delta {
+        &self.delta
+    }
+    // Changes made before the store was attached stay as they are, the
+    // store is only read for chunks that have none.
+    pub fn attach_store(&mut self, store: Store) {
+        self.fetched = self.delta.chunks().map(|(&pos, _)| pos).collect();
+        self.store = Some(store);
+    }
+    pub fn set_keep_distance(&mut self, chunks: i32) {
+        self.keep_distance = chunks;
+        self.center = None;
+    }
+    pub fn save(&mut self) -> io::Result<()> {
+        self.drop_falling();
+        let store = match self.store {
+            Some(ref mut store) => store,
+            None => return Ok(()),
+        };
+        store.write_level(self.seed, &self.generator)?;
+        let mut changed: HashSet<_> = self.delta.take_changed().into_iter().collect();
+        changed.extend(self.fluids.take_touched());
+        for (pos, cells) in self.fluids.chunks(&changed).into_iter() {
+            store.write_chunk(pos, self.delta.chunk(pos.0, pos.1, pos.2), &cells)?;
+        }
+        store.compact()
+    }
+    // Reads a chunk's saved changes into the delta and fluids the first time
+    // it is asked for.
+    fn fetch(&mut self, x: i32, y: i32, z: i32) {
+        let saved = match self.store {
+            Some(ref store) if !self.fetched.contains(&(x, y, z)) => store.read_chunk((x, y, z)),
+            _ => return,
+        };
+        self.fetched.insert((x, y, z));
+        match saved {
+            Ok(Some((spots, cells))) => {
+                self.delta.insert_chunk((x, y, z), spots);
+                self.fluids.insert_chunk((x, y, z), cells);
+            },
+            Ok(None) => {},
+            Err(e) => println!("Could not read chunk {:?}: {}", (x, y, z), e),
+        }
+    }
+    // Generating a chunk reads the changes in it and its six neighbours.
+    fn fetch_around(&mut self, x: i32, y: i32, z: i32) {
+        for &(dx, dy, dz) in delta::AROUND.iter() {
+            self.fetch(x + dx, y + dy, z + dz);
+        }
+    }
+    // Writes the changes in chunks out of range back to the store and lets
+    // go of them; they are fetched again when they are needed. Chunks that
+    // could not be written stay for the next try.
+    fn write_back<F>(&mut self, near: F) where F: Fn(i32, i32, i32) -> bool {
+        let store = match self.store {
+            Some(ref mut store) => store,
+            None => return,
+        };
+        let far: HashSet<_> = self.fetched.iter()
+            .filter(|&&(x, y, z)| !near(x, y, z))
+            .cloned().collect();
+        let mut gone = HashSet::new();
+        for (pos, cells) in self.fluids.chunks(&far).into_iter() {
+            if self.delta.is_changed(pos) || self.fluids.is_touched(pos) {
+                let spots = self.delta.chunk(pos.0, pos.1, pos.2);
+                if let Err(e) = store.write_chunk(pos, spots, &cells) {
+                    println!("Could not save chunk {:?}: {}", pos, e);
+                    continue;
+                }
+            }
+            gone.insert(pos);
+        }
+        // Rewriting a chunk appends a new copy, so the files are tidied here
+        // too and do not grow between saves.
+        if let Err(e) = store.compact() {
+            println!("Could not compact the save: {}", e);
+        }
+        for &pos in gone.iter() {
+            self.delta.remove_chunk(pos);
+            self.fetched.remove(&pos);
+        }
+        self.fluids.forget(&gone);
+    }
+    // Drops chunks further than keep_distance from pos, and writes their
+    // changes back to the store. Chunks holding changes, and their neighbours,
+    // are regenerated from the delta on the job pool as they come back in
+    // range, and so are chunks a cave waits to open into.
+    pub fn maintain(&mut self, pos: [f32;3]) {
+        self.collect();
+
+        let center = ((pos[0].floor() as i32) >> POT,
+                      (pos[1].floor() as i32) >> POT,
+                      (pos[2].floor() as i32) >> POT);
+        if self.center == Some(center) { return; }
+        self.center = Some(center);
+
+        let d = self.keep_distance;
+        let near = |x: i32, y: i32, z: i32| (x - center.0).abs() <= d
+                                         && (y - center.1).abs() <= d
+                                         && (z - center.2).abs() <= d;
+
+        let far: Vec<(i32, i32, i32)> = self.world.chunks.keys()
+            .filter(|&&(x, y, z)| !near(x, y, z))
+            .cloned().collect();
+        // Undo cannot reach into chunks that are gone, so the history that
+        // does goes with them.
+        if far.iter().any(|&c| self.journal.touches(c)) {
+            self.journal.clear();
+        }
+        for (x, y, z) in far.into_iter() {
+            self.world.remove_chunk(x, y, z);
+            self.surfacecache.remove(&(x, y, z));
+        }
+        self.write_back(&near);
+
+        // Chunks with changes, in memory or still only in the store.
+        let mut changed: Vec<_> = self.delta.chunks().map(|(&pos, _)| pos).collect();
+        if let Some(ref store) = self.store {
+            changed.extend(store.saved());
+        }
+        let mut wanted = Vec::new();
+        for (x, y, z) in changed.into_iter() {
+            for &(dx, dy, dz) in delta::AROUND.iter() {
+                let (x, y, z) = (x + dx, y + dy, z + dz);
+                if near(x, y, z) && !self.world.has_chunk(x, y, z) {
+                    wanted.push((x, y, z));
+                }
+            }
+        }
+        for &(x, y, z) in self.far_caves.keys() {
+            if near(x, y, z) && !self.world.has_chunk(x, y, z) {
+                wanted.push((x, y, z));
+            }
+        }
+        wanted.sort();
+        wanted.dedup();
+        for (x, y, z) in wanted.into_iter() {
+            self.request_chunk(x, y, z, false);
+        }
+    }
+    fn request_chunk(&mut self, x: i32, y: i32, z: i32, wait: bool) {
+        if let Some(waiting) = self.generating.get_mut(&(x, y, z)) {
+            *waiting |= wait;
+            return;
+        }
+        self.generating.insert((x, y, z), wait);
+        self.fetch_around(x, y, z);
+        self.pool.submit(Job::Generate {
+            pos: (x, y, z),
+            gen: self.gen.clone(),
+            delta: self.delta.around(x, y, z),
+        });
+    }
+    // Brings in a chunk ahead of maintain. Untouched ground is plain solid,
+    // anything else is generated on the pool, and waited for by an edit that
+    // needs it now.
+    fn load(&mut self, bx: i32, by: i32, bz: i32, wait: bool) {
+        self.fetch_around(bx, by, bz);
+        if self.delta.around(bx, by, bz).len() == 0 {
+            self.world.insert_chunk(Chunk::new_full(bx, by, bz));
+            self.observers.emit(WorldEvent::ChunkCreated { pos: (bx, by, bz) });
+            self.find_caves(bx, by, bz);
+            return;
+        }
+        self.request_chunk(bx, by, bz, wait);
+        while wait && self.generating.contains_key(&(bx, by, bz)) {
+            match self.pool.next() {
+                Some(done) => self.finish(done),
+                None => break,
+            }
+        }
+    }
+    fn in_range(&self, x: i32, y: i32, z: i32) -> bool {
+        match self.center {
+            Some((cx, cy, cz)) => (x - cx).abs() <= self.keep_distance
+                               && (y - cy).abs() <= self.keep_distance
+                               && (z - cz).abs() <= self.keep_distance,
+            None => true,
+        }
+    }
+    // Applies whatever the job pool has finished so far.
+    pub fn collect(&mut self) {
+        while let Some(done) = self.pool.try_next() {
+            self.finish(done);
+        }
+    }
+    // Blocks until every submitted job has been applied.
+    pub fn settle(&mut self) {
+        while !self.generating.is_empty() || !self.meshing.is_empty()
+              || !self.surfacing.is_empty() {
+            match self.pool.next() {
+                Some(done) => self.finish(done),
+                None => break,
+            }
+        }
+    }
+    // Results are only applied while the chunks they were computed from are
+    // unchanged, otherwise the work is redone, so the world ends up the same
+    // however the jobs were scheduled.
+    fn finish(&mut self, done: Done) {
+        match done {
+            Done::Generated(c) => {
+                let (x, y, z) = (c.bigpos[0], c.bigpos[1], c.bigpos[2]);
+                let waited = self.generating.remove(&(x, y, z)).unwrap_or(false);
+                if self.world.has_chunk(x, y, z) || !(waited || self.in_range(x, y, z)) { return; }
+                let touched = !c.is_solid();
+                self.world.insert_chunk(c);
+                self.observers.emit(WorldEvent::ChunkCreated { pos: (x, y, z) });
+                if touched {
+                    self.remesh_around(x, y, z);
+                }
+                self.find_caves(x, y, z);
+            },
+            Done::Meshed { pos, meshes, versions } => {
+                let (x, y, z) = pos;
+                let again = self.meshing.remove(&pos).unwrap_or(false);
+                if !self.world.has_chunk(x, y, z) { return; }
+                let fresh = versions == self.world.versions_around(x, y, z);
+                if fresh {
+                    self.world.set_meshes(x, y, z, meshes);
+                }
+                if again || !fresh {
+                    self.request_mesh(x, y, z);
+                }
+            },
+            Done::Surfaced { pos, vertices } => {
+                let (x, y, z) = pos;
+                let again = self.surfacing.remove(&pos).unwrap_or(false);
+                if !self.world.has_chunk(x, y, z) { return; }
+                // Edits, mesh jobs and new chunks all end up here. A chunk
+                // only marked dirty by a highlight drawn the same is not news.
+                let changed = self.surfacecache.get(&pos)
+                    .map_or(!vertices.is_empty(), |old| *old != vertices);
+                if changed {
+                    self.observers.emit(WorldEvent::ChunkRemeshed { pos });
+                }
+                self.surfacecache.insert(pos, vertices);
+                if again {
+                    self.request_surface(x, y, z);
+                }
+            },
+            Done::Failed { pos, kind } => {
+                println!("Lost a {:?} job for chunk {:?}", kind, pos);
+                match kind {
+                    Kind::Generate => self.generating.remove(&pos),
+                    Kind::Mesh => self.meshing.remove(&pos),
+                    Kind::Surface => self.surfacing.remove(&pos),
+                };
+            },
+        }
+    }
+    fn request_mesh(&mut self, x: i32, y: i32, z: i32) {
+        if let Some(again) = self.meshing.get_mut(&(x, y, z)) {
+            *again = true;
+            return;
+        }
+        self.meshing.insert((x, y, z), false);
+        self.pool.submit(Job::Mesh {
+            pos: (x, y, z),
+            world: self.world.snapshot(x, y, z),
+            tiles: self.tiles.clone(),
+            versions: self.world.versions_around(x, y, z),
+        });
+    }
+    fn request_surface(&mut self, x: i32, y: i32, z: i32) {
+        if let Some(again) = self.surfacing.get_mut(&(x, y, z)) {
+            *again = true;
+            return;
+        }
+        let chunk = match self.world.chunks.get(&(x, y, z)) {
+            Some(c) => c.clone(),
+            None => return,
+        };
+        self.surfacing.insert((x, y, z), false);
+        self.pool.submit(Job::Surface { pos: (x, y, z), chunk });
+    }
+    // Dug out spots in a new chunk open faces in its neighbours too, so
+    // those are meshed again along with it.
+    fn remesh_around(&mut self, bx: i32, by: i32, bz: i32) {
+        for x in bx-1..bx+2 { for y in by-1..by+2 { for z in bz-1..bz+2 {
+            let touched = self.world.get_chunk(x, y, z).map_or(false, |c| !c.is_solid());
+            if touched {
+                self.request_mesh(x, y, z);
+            }
+        }}}
+    }
+    fn splice_mut(&mut self, x: i32, y: i32, z: i32) -> (&mut Chunk, usize, usize, usize) {
+        let (bx, by, bz) = (x>>POT, y>>POT, z>>POT);
+        if !self.world.has_chunk(bx, by, bz) {
+            self.load(bx, by, bz, true);
+        }
+        self.world.splice_mut(x, y, z)
+    }
+    // The player's edits, recorded for undo. What the world does by itself,
+    // blocks falling, landing and caving in, goes straight to apply_spot so
+    // undo only steps through the player's own changes.
+    fn set_spot(&mut self, x: i32, y: i32, z: i32, s: Spot, mined: bool) -> bool {
+        let before = {
+            let (c, sx, sy, sz) = self.splice_mut(x, y, z);
+            c.at(sx, sy, sz).clone()
+        };
+        if before == s { return false; }
+        self.journal.record(Change { pos: (x, y, z), before, after: s.clone(), mined });
+        self.apply_spot(x, y, z, s);
+        true
+    }
+    // Every change to the world goes through here so the delta only ever
+    // holds spots that the generator would not reproduce on its own.
+    fn apply_spot(&mut self, x: i32, y: i32, z: i32, s: Spot) {
+        self.fetch(x >> POT, y >> POT, z >> POT);
+        // Before the delta changes, spot_at reads it for unloaded chunks.
+        let old = if self.observers.is_empty() { None } else { Some(self.spot_at(x, y, z)) };
+        if self.is_natural(x, y, z, &s) { self.delta.clear(x, y, z); }
+        else { self.delta.set(x, y, z, s.clone()); }
+
+        let empty = s.is_empty();
+        self.splice_mut(x, y, z);
+        self.world.set(x, y, z, s);
+
+        if let Some(old) = old {
+            let pos = (x, y, z);
+            let event = if empty { WorldEvent::BlockRemoved { pos, old } }
+                        else { WorldEvent::BlockPlaced { pos, old, new: self.spot_at(x, y, z) } };
+            self.observers.emit(event);
+        }
+
+        if empty { self.fluids.wake_around(x, y, z); }
+        else if self.fluids.at(x, y, z).is_some() { self.fluids.set(x, y, z, None); }
+
+        // Loose blocks check their footing on the next tick.
+        if empty { self.ticker.schedule((x, y + 1, z), 1); }
+        else { self.ticker.schedule((x, y, z), 1); }
+    }
+    // Whether the generator would put this spot here by itself.
+    fn is_natural(&self, x: i32, y: i32, z: i32, s: &Spot) -> bool {
+        match *s {
+            Empty => false,
+            Full => true,
+            Rich(ref b) => match self.gen.spot(x, y, z) {
+                Rich(g) => b.material_id() == g.material_id() && b.color() == g.color(),
+                _ => false,
+            },
+        }
+    }
+    fn reveal(&mut self, x: i32, y: i32, z: i32) {
+        let full = {
+            let (c, sx, sy, sz) = self.splice_mut(x, y, z);
+            if let &Full = c.at(sx, sy, sz) { true } else { false }
+        };
+        if full {
+            match self.gen.spot(x, y, z) {
+                Rich(b) => self.world.set(x, y, z, Rich(b)),
+                _ => {
+                    self.caves.push_back((x, y, z));
+                    self.open_caves(CAVE_STEP);
+                },
+            }
         }
     }
-    pub fn put(&mut self, rx: i32, ry: i32, rz: i32, b: Block){
-        b.update_surface(rx, ry, rz, &self.world, 1.0);
-        let (c, x, y, z) = self.world.splice_mut(rx, ry, rz);
-        c.put(x, y, z, b);
+    // Opens up to limit hidden cave spots from the queue, spreading through
+    // the cave, and reveals the walls around them. Opened air stays out of
+    // the delta, the cave is flooded again from where it was broken into
+    // when its chunks load. Spots out of range wait for their chunk to load.
+    fn open_caves(&mut self, limit: usize) {
+        let mut opened = Vec::new();
+        while opened.len() < limit {
+            let (x, y, z) = match self.caves.pop_front() {
+                Some(p) => p,
+                None => break,
+            };
+            let chunk = (x >> POT, y >> POT, z >> POT);
+            if !self.in_range(chunk.0, chunk.1, chunk.2) {
+                self.far_caves.entry(chunk).or_insert_with(HashSet::new).insert((x, y, z));
+                continue;
+            }
+            let hidden = {
+                let (c, sx, sy, sz) = self.splice_mut(x, y, z);
+                if let &Full = c.at(sx, sy, sz) { true } else { false }
+            };
+            if !hidden || !self.gen.is_cave(x, y, z) { continue; }
+            self.world.set(x, y, z, Empty);
+            opened.push((x, y, z));
+            for face in cube::FaceIterator::new() {
+                let d = face.direction();
+                let n = (x + d[0], y + d[1], z + d[2]);
+                if self.gen.is_cave(n.0, n.1, n.2) { self.caves.push_back(n); }
+            }
+        }
+
+        for &(x, y, z) in opened.iter() {
+            for face in cube::FaceIterator::new() {
+                let d = face.direction();
+                let (nx, ny, nz) = (x + d[0], y + d[1], z + d[2]);
+                if let Some(&Full) = self.world.at(nx, ny, nz) {
+                    if let Rich(b) = self.gen.spot(nx, ny, nz) {
+                        self.world.set(nx, ny, nz, Rich(b));
+                    }
+                }
+            }
+            self.fluids.wake_around(x, y, z);
+        }
+        self.update_surfaces_near(&opened);
+    }
+    // Queues the hidden cave spots that a newly loaded chunk opens onto: those
+    // a cave reached while the chunk was out of range, those beside air dug
+    // out in the chunk, and those in the chunk beside air in the loaded chunks
+    // around it.
+    fn find_caves(&mut self, bx: i32, by: i32, bz: i32) {
+        let mut found: Vec<(i32, i32, i32)> = match self.far_caves.remove(&(bx, by, bz)) {
+            Some(spots) => spots.into_iter().collect(),
+            None => Vec::new(),
+        };
+        found.sort();
+        if let Some(spots) = self.delta.chunk(bx, by, bz) {
+            for (&i, s) in spots.iter() {
+                if !s.is_empty() { continue; }
+                let i = i as i32;
+                let (x, y, z) = ((bx << POT) | ((i >> 8) & 15),
+                                 (by << POT) | ((i >> 4) & 15),
+                                 (bz << POT) | (i & 15));
+                for face in cube::FaceIterator::new() {
+                    let d = face.direction();
+                    found.push((x + d[0], y + d[1], z + d[2]));
+                }
+            }
+        }
+        let (ox, oy, oz) = (bx << POT, by << POT, bz << POT);
+        for a in 0..SIZE_I { for b in 0..SIZE_I { for &edge in [0, SIZE_I - 1].iter() {
+            let out = if edge == 0 { -1 } else { 1 };
+            for &(p, d) in [((edge, a, b), (out, 0, 0)), ((a, edge, b), (0, out, 0)),
+                            ((a, b, edge), (0, 0, out))].iter() {
+                let (x, y, z) = (ox + p.0, oy + p.1, oz + p.2);
+                if let Some(&Empty) = self.world.at(x + d.0, y + d.1, z + d.2) {
+                    found.push((x, y, z));
+                }
+            }
+        }}}
+        for (x, y, z) in found.into_iter() {
+            if let Some(&Full) = self.world.at(x, y, z) {
+                if self.gen.is_cave(x, y, z) { self.caves.push_back((x, y, z)); }
+            }
+        }
+    }
+    pub fn begin_edit(&mut self) {
+        self.journal.begin();
+    }
+    pub fn end_edit(&mut self) {
+        self.journal.end();
+    }
+    pub fn forget_history(&mut self) {
+        self.journal.clear();
+    }
+    // Undo and redo hand back the changes they went through, so whoever gave
+    // out drops for them can take them back.
+    pub fn undo(&mut self) -> Option<Vec<Change>> {
+        let group = self.journal.take_undo()?;
+        for change in group.iter().rev() {
+            let (x, y, z) = change.pos;
+            self.restore(x, y, z, change.before.clone());
+        }
+        Some(group)
+    }
+    pub fn redo(&mut self) -> Option<Vec<Change>> {
+        let group = self.journal.take_redo()?;
+        for change in group.iter() {
+            let (x, y, z) = change.pos;
+            self.restore(x, y, z, change.after.clone());
+        }
+        Some(group)
+    }
+    // Puts back a spot from the journal the way the edit itself did,
+    // revealing what a removal lays bare and bringing down what it held.
+    fn restore(&mut self, x: i32, y: i32, z: i32, s: Spot) {
+        let empty = s.is_empty();
+        self.apply_spot(x, y, z, s);
+        if empty {
+            for face in cube::FaceIterator::new() {
+                let d = face.direction();
+                self.reveal(x + d[0], y + d[1], z + d[2]);
+            }
+        }
+        self.update_surfaces_around(x, y, z);
+        if empty {
+            self.loosen(x, y + 1, z);
+            self.cave_in(&[(x, y, z)]);
+        }
+    }
+    // The spot as it is or would be, with natural blocks resolved to the
+    // block the generator puts there.
+    pub fn spot_at(&self, x: i32, y: i32, z: i32) -> Spot {
+        match self.world.at(x, y, z) {
+            Some(&Full) => self.gen.spot(x, y, z),
+            Some(s) => s.clone(),
+            None => {
+                let (chunk, i) = delta::split(x, y, z);
+                let saved = match self.store {
+                    Some(ref store) if !self.fetched.contains(&chunk) =>
+                        store.read_chunk(chunk).ok().and_then(|c| c)
+                            .and_then(|(mut spots, _)| spots.remove(&i)),
+                    _ => self.delta.get(x, y, z).cloned(),
+                };
+                saved.unwrap_or_else(|| self.gen.spot(x, y, z))
+            },
+        }
+    }
+    // Empties the spot whether or not it was revealed.
+    pub fn carve(&mut self, x: i32, y: i32, z: i32) -> bool {
+        self.bulk(vec![((x, y, z), Empty)]) > 0
+    }
+    pub fn fill(&mut self, shape: &Shape, b: &Block) -> usize {
+        let edits = shape.positions().into_iter()
+            .map(|p| (p, Rich(b.clone())))
+            .collect();
+        self.bulk(edits)
+    }
+    pub fn clear(&mut self, shape: &Shape) -> usize {
+        let edits = shape.positions().into_iter()
+            .map(|p| (p, Empty))
+            .collect();
+        self.bulk(edits)
+    }
+    // Empties the inside of the shape, leaving a one block thick shell.
+    pub fn hollow(&mut self, shape: &Shape) -> usize {
+        let edits = shape.positions().into_iter()
+            .filter(|&(x, y, z)| !shape.is_edge(x, y, z))
+            .map(|p| (p, Empty))
+            .collect();
+        self.bulk(edits)
+    }
+    pub fn replace(&mut self, shape: &Shape, from: MaterialId, b: &Block) -> usize {
+        let mut edits = Vec::new();
+        for (x, y, z) in shape.positions().into_iter() {
+            let matches = match self.world.at(x, y, z) {
+                Some(&Rich(ref old)) => old.material_id() == from,
+                Some(&Empty) => false,
+                _ => match self.gen.spot(x, y, z) {
+                    Rich(g) => g.material_id() == from,
+                    _ => false,
+                },
+            };
+            if matches {
+                edits.push(((x, y, z), Rich(b.clone())));
+            }
+        }
+        self.bulk(edits)
+    }
+    // Applies many edits as one undo step, then reveals and remeshes each
+    // affected spot once rather than once per edit.
+    fn bulk(&mut self, edits: Vec<((i32, i32, i32), Spot)>) -> usize {
+        self.journal.begin();
+        let mut changed = Vec::new();
+        for ((x, y, z), s) in edits.into_iter() {
+            if self.set_spot(x, y, z, s, false) {
+                changed.push((x, y, z));
+            }
+        }
+
+        let mut emptied = Vec::new();
+        for &(x, y, z) in changed.iter() {
+            if let Some(&Empty) = self.world.at(x, y, z) {
+                for face in cube::FaceIterator::new() {
+                    let d = face.direction();
+                    self.reveal(x + d[0], y + d[1], z + d[2]);
+                }
+                emptied.push((x, y, z));
+            }
+        }
+        self.update_surfaces_near(&changed);
+        for &(x, y, z) in emptied.iter() {
+            self.loosen(x, y + 1, z);
+        }
+        self.cave_in(&emptied);
+        self.journal.end();
+        changed.len()
+    }
+    // Sends the column of loose blocks resting on (x, y, z) falling.
+    fn loosen(&mut self, x: i32, y: i32, z: i32) {
+        let mut y = y;
+        loop {
+            let b = match self.spot_at(x, y, z) {
+                Rich(b) => b,
+                _ => break,
+            };
+            if !b.material().falls { break; }
+            self.apply_spot(x, y, z, Empty);
+            for face in cube::FaceIterator::new() {
+                let d = face.direction();
+                self.reveal(x + d[0], y + d[1], z + d[2]);
+            }
+            self.update_surfaces_around(x, y, z);
+            self.falling.push(FallingBlock::new(b, x, y, z));
+            y += 1;
+        }
+    }
+    pub fn falling(&self) -> &[FallingBlock] {
+        &self.falling
+    }
+    // Moves falling blocks down and settles them into the grid once they hit
+    // the ground. A block coming down on the body rests on top of it, and the
+    // damage of the blows is returned.
+    pub fn update_falling(&mut self, dt: f32, body: ([f64;3], [f64;3])) -> f32 {
+        let mut damage = 0.0;
+        let mut falling = ::std::mem::replace(&mut self.falling, Vec::new());
+        falling.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap_or(::std::cmp::Ordering::Equal));
+
+        for mut f in falling.into_iter() {
+            // Hurts on the frame the block hits, not while it rests on the body.
+            let impact = f.vel;
+            let ny = f.next_y(dt);
+            if f.overlaps(ny, body.0, body.1) {
+                let top = body.1[1] as f32;
+                if f.y >= top { f.y = top; }
+                damage += impact * DEBRIS_DAMAGE;
+                f.vel = 0.0;
+                self.falling.push(f);
+                continue;
+            }
+
+            // The first spot below that is not open air, and whether it is
+            // in a chunk that is not loaded.
+            let (top, bottom) = (f.y.floor() as i32, ny.floor() as i32);
+            let mut stop = None;
+            for y in (bottom..top).rev() {
+                match self.world.at(f.x, y, f.z) {
+                    Some(&Empty) => {},
+                    s => { stop = Some((y, s.is_none())); break; },
+                }
+            }
+            match stop {
+                // Nothing is known of what is below, so the block waits for
+                // it to load.
+                Some((y, true)) => {
+                    f.y = (y + 1) as f32;
+                    f.vel = 0.0;
+                    let (bx, by, bz) = (f.x >> POT, y >> POT, f.z >> POT);
+                    if self.in_range(bx, by, bz) { self.load(bx, by, bz, false); }
+                    self.falling.push(f);
+                },
+                Some((y, false)) => self.land(f, y + 1),
+                None => {
+                    f.y = ny;
+                    self.falling.push(f);
+                },
+            }
+        }
+        damage
+    }
+    // Lands every falling block straight away so none are lost when saving.
+    // Blocks over chunks that are not loaded land on the last spot known to
+    // be open.
+    fn drop_falling(&mut self) {
+        let falling = ::std::mem::replace(&mut self.falling, Vec::new());
+        for f in falling.into_iter() {
+            let top = f.y.floor() as i32;
+            let y = match (top-SIZE_I..top).rev().find(|&y| !self.is_open(f.x, y, f.z)) {
+                Some(y) => y + 1,
+                None => top - SIZE_I,
+            };
+            self.land(f, y);
+        }
+    }
+    // Puts a landed block into the lowest empty spot at or above y. A block
+    // with nowhere to go, when something was built into its path, is kept
+    // falling rather than lost.
+    fn land(&mut self, f: FallingBlock, y: i32) {
+        match (y..y+SIZE_I).find(|&y| self.is_open(f.x, y, f.z)) {
+            Some(y) => {
+                self.apply_spot(f.x, y, f.z, Rich(f.block));
+                self.update_surfaces_around(f.x, y, f.z);
+            },
+            None => self.falling.push(f),
+        }
+    }
+    fn is_open(&self, x: i32, y: i32, z: i32) -> bool {
+        if let Some(&Empty) = self.world.at(x, y, z) { true } else { false }
+    }
+    pub fn set_tiles(&mut self, tiles: Tiles) {
+        self.tiles = Arc::new(tiles);
+        self.refresh();
+    }
+    pub fn put(&mut self, x: i32, y: i32, z: i32, b: Block){
+        self.set_spot(x, y, z, Rich(b), false);
+        self.update_surfaces_around(x, y, z);
     }
     pub fn yank(&mut self, x: i32, y: i32, z: i32) -> Option<Block>{
-        let (c, sx, sy, sz) = self.world.splice_mut(x, y, z);
-        c.yank(sx, sy, sz)
+        let (c, sx, sy, sz) = self.splice_mut(x, y, z);
+        if let Rich(b) = c.at(sx, sy, sz).clone() {
+            self.set_spot(x, y, z, Empty, true);
+            Some(b)
+        } else {
+            None
+        }
     }
     pub fn pull(&mut self, x: i32, y: i32, z: i32) -> Option<Block>{
         let ret = self.yank(x,y,z);
         for face in cube::FaceIterator::new() {
             let d = face.direction();
-            let (dx, dy, dz) = (x + d[0], y + d[1], z + d[2]);
-            let (c, ux, uy, uz) = self.world.splice_mut(dx,dy,dz);
-            if let b @ &mut Full = c.at_mut(ux, uy, uz){
-                let block = Rich(Box::new(self.gen.at(dx,dy,dz)));
-                *b = block;
-            }
-        }
-        for dx in x-1..x+2 { for dy in y-1..y+2 { for dz in z-1..z+2 {
-            if let Some(s) = self.world.at_update(dx,dy,dz) {
-            if let &Rich(ref b) = s {
-                b.update_surface(dx, dy, dz, &self.world, 1.0);
-            }}
-        }}}
+            self.reveal(x + d[0], y + d[1], z + d[2]);
+        }
+        self.update_surfaces_around(x, y, z);
+        if let Some(ref b) = ret {
+            let dug = Rich(b.clone());
+            if self.is_natural(x, y, z, &dug) { self.breach(x, y, z); }
+            self.loosen(x, y + 1, z);
+            self.cave_in(&[(x, y, z)]);
+        }
         ret
     }
-    pub fn viewcast(&self, pos: [f32;3], dir: [f32;3])
-            -> (Option<(i32, i32, i32)>, Option<(i32, i32, i32)>){
-        let mut full = None;
-        let mut empty = None;
-
-        use line_drawing::{ VoxelOrigin, WalkVoxels };
-        use vecmath;
-        let dir = vecmath::vec3_neg(dir);
-        let dir = vecmath::vec3_scale(dir, 10.0);
-        let end = vecmath::vec3_add(pos, dir);
-
-        let mut temp = None;
-        for (_, (x, y, z)) in WalkVoxels::<f32, i32>::new(
-                        (pos[0], pos[1], pos[2]),
-                        (end[0], end[1], end[2]),
-                        &VoxelOrigin::Corner)
-                        .enumerate() {
-
-            if let Some(b) = self.world.at(x, y, z){
-                if b.is_rich() {
-                    empty = temp;
-                    full = Some((x, y, z));
-                    break;
-                } else {
-                    temp = Some((x, y, z));
+    fn span_at(&self, x: i32, y: i32, z: i32) -> Option<i32> {
+        match self.world.at(x, y, z) {
+            Some(&Empty) => None,
+            Some(&Rich(ref b)) => Some(b.material().span),
+            // Hidden caves hold like rock until they are opened up.
+            Some(&Full) => Some(material::get(self.gen.material_at(x, y, z)).span),
+            None => Some(MAX_SPAN),
+        }
+    }
+    // A spot standing on a beam carries as far as the beam would.
+    fn probe(&self, x: i32, y: i32, z: i32) -> Option<Probe> {
+        let span = self.span_at(x, y, z)?;
+        Some(match self.span_at(x, y - 1, z) {
+            Some(below) => Probe { span: span.max(below), grounded: true },
+            None => Probe { span, grounded: false },
+        })
+    }
+    // Brings down whatever lost its hold when the spots were dug out: the
+    // spots above them and the layers they were part of, then whatever
+    // rested on those.
+    fn cave_in(&mut self, dug: &[(i32, i32, i32)]) {
+        let mut checks = Vec::new();
+        for &(x, y, z) in dug.iter() {
+            for &p in [(x, y + 1, z), (x + 1, y, z), (x - 1, y, z), (x, y, z + 1), (x, y, z - 1)].iter() {
+                if !self.is_open(p.0, p.1, p.2) { checks.push(p); }
+            }
+        }
+        checks.sort();
+        checks.dedup();
+        checks.reverse();
+        let mut fallen = Vec::new();
+        while let Some(start) = checks.pop() {
+            if fallen.len() >= COLLAPSE_LIMIT { break; }
+            for (fx, fy, fz) in support::unsupported(start, |x, y, z| self.probe(x, y, z)).into_iter() {
+                if fallen.len() >= COLLAPSE_LIMIT { break; }
+                let b = match self.spot_at(fx, fy, fz) {
+                    Rich(b) => b,
+                    _ => continue,
+                };
+                self.apply_spot(fx, fy, fz, Empty);
+                self.falling.push(FallingBlock::new(b, fx, fy, fz));
+                checks.push((fx, fy + 1, fz));
+                fallen.push((fx, fy, fz));
+            }
+        }
+
+        for &(x, y, z) in fallen.iter() {
+            for face in cube::FaceIterator::new() {
+                let d = face.direction();
+                self.reveal(x + d[0], y + d[1], z + d[2]);
+            }
+        }
+        self.update_surfaces_near(&fallen);
+    }
+    // Digging out ground that holds fluid lets it out.
+    fn breach(&mut self, x: i32, y: i32, z: i32) {
+        if let Some(fluid) = self.gen.fluid_at(x, y, z) {
+            self.fluids.set(x, y, z, Some(Cell { fluid, level: MAX_LEVEL }));
+        }
+    }
+    pub fn fluid_at(&self, x: i32, y: i32, z: i32) -> Option<Cell> {
+        self.fluids.at(x, y, z)
+    }
+    // Every change from here on is sent down the returned channel.
+    pub fn subscribe(&mut self) -> Receiver<WorldEvent> {
+        self.observers.subscribe()
+    }
+    pub fn schedule(&mut self, x: i32, y: i32, z: i32, delay: u64) {
+        self.ticker.schedule((x, y, z), delay);
+    }
+    pub fn tick_count(&self) -> u64 {
+        self.ticker.now()
+    }
+    // Runs the ticks dt covers: spots scheduled for them, a few random spots
+    // in every loaded chunk, and the fluids every FLUID_EVERY ticks.
+    pub fn update_ticks(&mut self, dt: f32) {
+        for _ in 0..self.ticker.advance(dt) {
+            for (x, y, z) in self.ticker.step().into_iter() {
+                self.on_tick(x, y, z, TickKind::Scheduled);
+            }
+
+            let mut loaded: Vec<[i32;3]> = self.world.iter().map(|c| c.bigpos).collect();
+            loaded.sort();
+            for b in loaded.into_iter() {
+                for _ in 0..RANDOM_TICKS {
+                    let i = self.ticker.random_index() as i32;
+                    self.on_tick((b[0] << POT) | ((i >> 8) & 15),
+                                 (b[1] << POT) | ((i >> 4) & 15),
+                                 (b[2] << POT) | (i & 15), TickKind::Random);
                 }
-            } else {
-                temp = Some((x, y, z));
             }
+
+            if !self.caves.is_empty() {
+                self.open_caves(CAVE_STEP);
+            }
+
+            if self.ticker.now() % FLUID_EVERY == 0 {
+                self.fluids.tick(&self.world);
+            }
+        }
+        if self.fluids.take_changed() {
+            self.fluidcache = self.fluids.surface(&self.world, self.tiles.get(material::STONE));
+        }
+    }
+    fn on_tick(&mut self, x: i32, y: i32, z: i32, kind: TickKind) {
+        let falls = match self.world.at(x, y, z) {
+            Some(&Rich(ref b)) => b.material().falls,
+            _ => false,
+        };
+        if falls && self.is_open(x, y - 1, z) {
+            self.loosen(x, y, z);
+        }
+        // Shallow puddles dry up over time.
+        if kind == TickKind::Random {
+            if let Some(Cell { fluid: Fluid::Water, level: 1 }) = self.fluids.at(x, y, z) {
+                self.fluids.set(x, y, z, None);
+            }
+        }
+    }
+    fn update_surface(&mut self, x: i32, y: i32, z: i32, shine: f32) {
+        let quads = match self.world.at(x, y, z) {
+            Some(&Rich(ref b)) => {
+                let tile = self.tiles.get(b.material_id());
+                b.surface(x, y, z, &self.world, tile, shine)
+            },
+            _ => return,
+        };
+        let (c, sx, sy, sz) = self.world.splice_mut(x, y, z);
+        c.set_mesh(sx, sy, sz, quads);
+    }
+    fn update_surfaces_around(&mut self, x: i32, y: i32, z: i32) {
+        self.update_surfaces_near(&[(x, y, z)]);
+    }
+    // Updates every spot next to any of the given ones, each only once.
+    fn update_surfaces_near(&mut self, spots: &[(i32, i32, i32)]) {
+        let mut dirty = HashSet::new();
+        for &(x, y, z) in spots.iter() {
+            for dx in x-1..x+2 { for dy in y-1..y+2 { for dz in z-1..z+2 {
+                dirty.insert((dx, dy, dz));
+            }}}
+        }
+        for (x, y, z) in dirty.into_iter() {
+            self.update_surface(x, y, z, 1.0);
+        }
+    }
+    pub fn viewcast(&self, pos: [f32;3], dir: [f32;3])
+            -> (Option<(i32, i32, i32)>, Option<(i32, i32, i32)>){
+        let dir = [-dir[0], -dir[1], -dir[2]];
+        match self.world.raycast(pos, dir, VIEW_REACH, |s| s.is_rich()) {
+            Some(hit) => {
+                let (x, y, z) = hit.pos;
+                let n = hit.normal;
+                let empty = if n == [0; 3] { None } else { Some((x + n[0], y + n[1], z + n[2])) };
+                (Some(hit.pos), empty)
+            },
+            None => (None, None),
         }
-        (full, empty)
     }
     pub fn set_shiny(&mut self, x: i32, y: i32, z: i32, shine: f32) {
-        if let Some(s) = self.world.at_update(x,y,z) {
-        if let &Rich(ref b) = s {
-            b.update_surface(x, y, z, &self.world, shine);
-        }}
+        self.update_surface(x, y, z, shine);
         self.shiny.push((x,y,z,shine));
     }
     pub fn clear_shiny(&mut self) {
         for i in 0..self.shiny.len() {
             let (x, y, z, _) = self.shiny[i];
-            if let Some(s) = self.world.at_update(x,y,z) {
-            if let &Rich(ref b) = s {
-                b.update_surface(x, y, z, &self.world, 1.0);
-            }}
+            self.update_surface(x, y, z, 1.0);
         }
         self.shiny = Vec::new();
     }
+    // Draws the surfaces finished so far. Changed chunks are merged into new
+    // surfaces on the job pool and show up on a later frame.
     pub fn get_vertex_data(&mut self) -> (Vec<Vertex>, Vec<u32>){
+        self.collect();
         let mut vertex_data = Vec::new();
-        let mut updates = Vec::new();
-        for c in self.world.chunks() {
-            if *c.request.borrow() {
-                updates.push((c.bigpos, c.build_surface()));
-                *c.request.borrow_mut() = false;
-            }
-        };
-
-        for u in updates.into_iter(){
-            let (pos, vertices) = u;
-            let (x, y, z) = (pos[0], pos[1], pos[2]);
-            self.surfacecache.insert((x,y,z), vertices);
+        for (x, y, z) in self.world.take_dirty().into_iter() {
+            self.request_surface(x, y, z);
         }
 
         for v in self.surfacecache.values_mut() {
             vertex_data.extend_from_slice(v.as_slice());
         }
+        vertex_data.extend_from_slice(self.fluidcache.as_slice());
+        for f in self.falling.iter() {
+            let tile = self.tiles.get(f.block.material_id());
+            vertex_data.extend(f.block.cube([f.x as f32, f.y, f.z as f32], tile));
+        }
 
         let mut index_data = Vec::new();
         for i in 0..(vertex_data.len() / 4) {
@@ -407,9 +1552,156 @@ impl Milieu {
 
         (vertex_data, index_data)
     }
-    /*pub fn refresh(&mut self){
-        for v in self.chunks.values_mut(){
-            v.build_surface();
+    // Remeshes every loaded chunk on the job pool, e.g. after the tiles changed.
+    pub fn refresh(&mut self){
+        let loaded: Vec<(i32, i32, i32)> = self.world.chunks.keys().cloned().collect();
+        for (x, y, z) in loaded.into_iter() {
+            self.request_mesh(x, y, z);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tinted(i: usize) -> Spot {
+        Rich(Block::from_parts(material::STONE, [i as f32 / 256.0, 0.5, 0.5, 1.0], [0; 6]))
+    }
+
+    // Spots in a fixed but scattered order, walked by a step coprime to VOLUME.
+    fn scattered(n: usize) -> Vec<(usize, usize, usize)> {
+        (0..n).map(|k| {
+            let i = (k * 1237) % VOLUME;
+            ((i >> 8) & 15, (i >> 4) & 15, i & 15)
+        }).collect()
+    }
+
+    #[test]
+    fn palette_grows() {
+        let mut c = Chunk::new_full(0, 0, 0);
+        assert_eq!(c.bits, 0);
+        assert!(c.packed.is_empty());
+
+        // Full is the first entry, so n distinct blocks make n + 1 entries.
+        let spots = scattered(16);
+        for (n, &(x, y, z)) in spots.iter().enumerate() {
+            c.set(x, y, z, tinted(n));
+            let bits = match c.palette.len() {
+                2 => 1,
+                3..=4 => 2,
+                5..=16 => 4,
+                _ => 8,
+            };
+            assert_eq!(c.bits, bits, "{} entries", c.palette.len());
+            assert_eq!(c.packed.len(), VOLUME / (64 / bits));
+        }
+        assert_eq!(c.palette.len(), 17);
+        for (n, &(x, y, z)) in spots.iter().enumerate() {
+            assert_eq!(*c.at(x, y, z), tinted(n));
+        }
+        assert_eq!(*c.at(15, 15, 15), Full);
+    }
+
+    #[test]
+    fn at_after_many_sets() {
+        let mut c = Chunk::new_full(0, 0, 0);
+        let mut expected = vec![Full; VOLUME];
+        // Overwrites each spot a few times and frees palette entries on the way.
+        for (k, &(x, y, z)) in scattered(VOLUME * 3).iter().enumerate() {
+            let s = match k % 5 {
+                0 => Empty,
+                1 => Full,
+                _ => tinted(k % 40),
+            };
+            expected[index(x, y, z)] = s.clone();
+            c.set(x, y, z, s);
+        }
+        for x in 0..SIZE_U { for y in 0..SIZE_U { for z in 0..SIZE_U {
+            assert_eq!(*c.at(x, y, z), expected[index(x, y, z)], "{} {} {}", x, y, z);
+        }}}
+        assert!(!c.is_solid());
+    }
+
+    #[test]
+    fn reintern_keeps_palette() {
+        let mut c = Chunk::new_full(0, 0, 0);
+        c.set(0, 0, 0, tinted(1));
+        c.set(1, 0, 0, tinted(2));
+        let (len, bits) = (c.palette.len(), c.bits);
+        for &(x, y, z) in scattered(200).iter() {
+            c.set(x, y, z, tinted(1));
+        }
+        c.set(0, 0, 0, tinted(2));
+        assert_eq!((c.palette.len(), c.bits), (len, bits));
+        // A freed entry is reused before the palette grows.
+        c.set(0, 0, 0, tinted(1));
+        c.set(1, 0, 0, tinted(1));
+        c.set(2, 0, 0, tinted(3));
+        assert_eq!((c.palette.len(), c.bits), (len, bits));
+        assert_eq!(*c.at(2, 0, 0), tinted(3));
+    }
+
+    // Chunk (0, 0, 0) dug out, with solid spots at the given places.
+    fn room(solid: &[(i32, i32, i32)]) -> InfiniteWorld {
+        let mut w = InfiniteWorld::new_full();
+        for x in 0..SIZE_I { for y in 0..SIZE_I { for z in 0..SIZE_I {
+            w.set(x, y, z, Empty);
+        }}}
+        for &(x, y, z) in solid.iter() {
+            w.set(x, y, z, Full);
         }
-    }*/
-}
\ No newline at end of file
+        w
+    }
+
+    fn solid(s: &Spot) -> bool {
+        *s != Empty
+    }
+
+    #[test]
+    fn raycast_axis_aligned() {
+        let w = room(&[(8, 4, 4), (3, 10, 3)]);
+
+        let hit = w.raycast([2.5, 4.5, 4.5], [1.0, 0.0, 0.0], 10.0, solid).unwrap();
+        assert_eq!((hit.pos, hit.normal), ((8, 4, 4), [-1, 0, 0]));
+        assert_eq!(hit.distance, 5.5);
+        assert_eq!(hit.point, [8.0, 4.5, 4.5]);
+
+        let hit = w.raycast([12.5, 4.5, 4.5], [-3.0, 0.0, 0.0], 10.0, solid).unwrap();
+        assert_eq!((hit.pos, hit.normal, hit.distance), ((8, 4, 4), [1, 0, 0], 3.5));
+
+        let hit = w.raycast([3.5, 2.5, 3.5], [0.0, 1.0, 0.0], 10.0, solid).unwrap();
+        assert_eq!((hit.pos, hit.normal, hit.distance), ((3, 10, 3), [0, -1, 0], 7.5));
+        assert!(w.raycast([3.5, 2.5, 3.5], [0.0, 1.0, 0.0], 7.0, solid).is_none());
+
+        // Starting inside a solid spot hits it at once, with no face.
+        let hit = w.raycast([8.2, 4.9, 4.1], [0.0, 0.0, -1.0], 10.0, solid).unwrap();
+        assert_eq!((hit.pos, hit.normal, hit.distance), ((8, 4, 4), [0, 0, 0], 0.0));
+    }
+
+    #[test]
+    fn raycast_order() {
+        let w = room(&[(5, 5, 4), (9, 5, 4)]);
+        // The nearer of two spots on the way is the one hit.
+        let hit = w.raycast([1.5, 5.5, 4.5], [1.0, 0.0, 0.0], 20.0, solid).unwrap();
+        assert_eq!(hit.pos, (5, 5, 4));
+        let hit = w.raycast([0.5, 0.5, 4.5], [1.0, 1.0, 0.0], 20.0, solid).unwrap();
+        assert_eq!(hit.pos, (5, 5, 4));
+        assert!((hit.distance - 4.5 * 2f32.sqrt()).abs() < 1e-4);
+        // Spots outside loaded chunks are passed over.
+        let hit = w.raycast([-20.5, 5.5, 4.5], [1.0, 0.0, 0.0], 40.0, solid).unwrap();
+        assert_eq!((hit.pos, hit.distance), ((5, 5, 4), 25.5));
+    }
+
+    #[test]
+    fn raycast_degenerate() {
+        let w = room(&[(8, 4, 4)]);
+        let nan = ::std::f32::NAN;
+        let inf = ::std::f32::INFINITY;
+        for &dir in [[0.0, 0.0, 0.0], [nan, 0.0, 0.0], [1.0, nan, 0.0],
+                     [nan, nan, nan], [inf, 0.0, 0.0]].iter() {
+            assert!(w.raycast([2.5, 4.5, 4.5], dir, inf, solid).is_none(), "{:?}", dir);
+        }
+        assert!(w.raycast([2.5, 4.5, 4.5], [1.0, 0.0, 0.0], nan, solid).is_none());
+        assert!(w.raycast([2.5, 4.5, 4.5], [1.0, 0.0, 0.0], -1.0, solid).is_none());
+    }
+}